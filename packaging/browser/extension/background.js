@@ -0,0 +1,31 @@
+// Report the active tab of the focused browser window to QMKonnect
+const HOST = 'io.github.qmkonnect';
+
+let port = null;
+
+function connect() {
+  port = chrome.runtime.connectNative(HOST);
+  port.onDisconnect.addListener(() => {
+    port = null;
+  });
+}
+
+async function report() {
+  const [tab] = await chrome.tabs.query({ active: true, lastFocusedWindow: true });
+  if (!tab || !tab.url) {
+    return;
+  }
+  if (!port) {
+    connect();
+  }
+  port.postMessage({ url: tab.url });
+}
+
+chrome.tabs.onActivated.addListener(report);
+chrome.tabs.onUpdated.addListener((tabId, change, tab) => {
+  if (change.url && tab.active) {
+    report();
+  }
+});
+chrome.windows.onFocusChanged.addListener(report);
+report();
@@ -0,0 +1,50 @@
+import Gio from 'gi://Gio';
+import GLib from 'gi://GLib';
+import {Extension} from 'resource:///org/gnome/shell/extensions/extension.js';
+
+// Must match the D-Bus object exported by qmkonnect's GNOME backend
+const BUS_NAME = 'io.github.qmkonnect';
+const OBJECT_PATH = '/io/github/qmkonnect/Gnome';
+const INTERFACE_NAME = 'io.github.qmkonnect.Gnome';
+
+export default class QmkonnectExtension extends Extension {
+    enable() {
+        this._window = null;
+        this._titleHandlerId = 0;
+        this._focusHandlerId = global.display.connect('notify::focus-window',
+            () => this._onFocusChanged());
+        this._onFocusChanged();
+    }
+
+    disable() {
+        global.display.disconnect(this._focusHandlerId);
+        this._focusHandlerId = 0;
+        this._trackWindow(null);
+    }
+
+    _onFocusChanged() {
+        this._trackWindow(global.display.focus_window);
+        this._report();
+    }
+
+    // Follow title changes of the focused window only
+    _trackWindow(window) {
+        if (this._window && this._titleHandlerId)
+            this._window.disconnect(this._titleHandlerId);
+
+        this._window = window;
+        this._titleHandlerId = window
+            ? window.connect('notify::title', () => this._report())
+            : 0;
+    }
+
+    _report() {
+        const wmClass = this._window?.get_wm_class() ?? '';
+        const title = this._window?.get_title() ?? '';
+
+        // Fire and forget: qmkonnect may simply not be running
+        Gio.DBus.session.call(BUS_NAME, OBJECT_PATH, INTERFACE_NAME, 'FocusChanged',
+            new GLib.Variant('(ss)', [wmClass, title]), null,
+            Gio.DBusCallFlags.NO_AUTO_START, -1, null, null);
+    }
+}
@@ -0,0 +1,44 @@
+-- Report Neovim's mode to QMKonnect. Copy to ~/.config/nvim/plugin/qmkonnect.lua
+-- and set `mode = true` under [encoder] in QMKonnect's configuration.
+local socket = vim.g.qmkonnect_socket
+  or (vim.env.XDG_RUNTIME_DIR or vim.env.TMPDIR or '/tmp') .. '/qmkonnect/nvim.sock'
+
+local channel = nil
+
+local function send(mode)
+  if channel == nil then
+    local ok, id = pcall(vim.fn.sockconnect, 'pipe', socket)
+    if not ok or id == 0 then
+      return
+    end
+    channel = id
+  end
+  -- QMKonnect may have restarted since we connected
+  if not pcall(vim.fn.chansend, channel, vim.json.encode({ mode = mode }) .. '\n') then
+    channel = nil
+  end
+end
+
+local group = vim.api.nvim_create_augroup('qmkonnect', { clear = true })
+
+vim.api.nvim_create_autocmd('ModeChanged', {
+  group = group,
+  callback = function()
+    send(vim.v.event.new_mode)
+  end,
+})
+
+-- With several instances open, the focused one decides
+vim.api.nvim_create_autocmd({ 'VimEnter', 'FocusGained' }, {
+  group = group,
+  callback = function()
+    send(vim.api.nvim_get_mode().mode)
+  end,
+})
+
+vim.api.nvim_create_autocmd('VimLeavePre', {
+  group = group,
+  callback = function()
+    send('')
+  end,
+})
@@ -0,0 +1,476 @@
+#![cfg(unix)]
+// Control socket of the running daemon. Clients send one JSON request per
+// line, e.g. {"command":"pause"}, and get one JSON response line back.
+// After {"command":"subscribe"} the connection carries one line per window
+// event instead, until the client hangs up.
+use crate::core;
+use crate::core::notifier::{self, WindowEvent};
+use crate::core::types::WindowInfo;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long a pushed window stands in for focus unless the request says otherwise
+const DEFAULT_PUSH_SECONDS: u64 = 60;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    // Whether we run, on what, and whether paused
+    Status,
+    // Last focused window and the message it was sent as
+    Current,
+    Pause,
+    Resume,
+    // Pause if running, resume if paused
+    Toggle,
+    // Load the configuration file again
+    Reload,
+    // Send a message to the keyboard as is
+    Send {
+        message: String,
+    },
+    // Send this context regardless of focus, for a while or until cleared
+    Override {
+        app_class: String,
+        #[serde(default)]
+        title: String,
+        seconds: Option<u64>,
+    },
+    #[serde(rename = "clear-override")]
+    ClearOverride,
+    // Stream every window event from now on
+    Subscribe,
+    // Stand in for the focused window, replacing what the source pushed before
+    Push {
+        #[serde(default = "default_source")]
+        source: String,
+        app_class: String,
+        #[serde(default)]
+        title: String,
+        #[serde(default)]
+        fields: BTreeMap<String, String>,
+        #[serde(default)]
+        priority: u32,
+        seconds: Option<u64>,
+    },
+    #[serde(rename = "clear-push")]
+    ClearPush {
+        #[serde(default = "default_source")]
+        source: String,
+    },
+}
+
+pub fn default_source() -> String {
+    "push".to_string()
+}
+
+fn socket_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(core::runtime_dir()?.join("control.sock"))
+}
+
+// Serve the control socket in the background for as long as the daemon runs
+pub fn start(platform: &str, verbose: bool) {
+    let platform = platform.to_string();
+    let listener = match socket_path().and_then(|path| Ok(bind(&path)?)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not open the control socket: {}", e);
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let platform = platform.clone();
+            thread::spawn(move || serve(stream, &platform, verbose, notifier::subscribe));
+        }
+    });
+}
+
+fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    // Don't pull the socket from under another running daemon
+    if UnixStream::connect(path).is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            "QMKonnect is already running",
+        ));
+    }
+    // Left behind by a previous run
+    let _ = fs::remove_file(path);
+    UnixListener::bind(path)
+}
+
+// Subscribers get the events of the receiver subscribe returns
+fn serve<F>(stream: UnixStream, platform: &str, verbose: bool, subscribe: F)
+where
+    F: FnOnce() -> Receiver<WindowEvent>,
+{
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Subscribe) => return stream_events(writer, subscribe(), verbose),
+            Ok(request) => handle(request, platform, verbose),
+            Err(e) => error(format!("Invalid request: {}", e)),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+fn stream_events(mut writer: UnixStream, events: Receiver<WindowEvent>, verbose: bool) {
+    if verbose {
+        println!("Control request: Subscribe");
+    }
+    if writeln!(writer, "{}", json!({ "ok": true })).is_err() {
+        return;
+    }
+    for event in events {
+        let Ok(line) = serde_json::to_string(&event) else {
+            continue;
+        };
+        if writeln!(writer, "{}", line).is_err() {
+            return;
+        }
+    }
+}
+
+fn handle(request: Request, platform: &str, verbose: bool) -> Value {
+    if verbose {
+        println!("Control request: {:?}", request);
+    }
+
+    let result = match request {
+        Request::Status => {
+            let pinned = notifier::pinned().map(|pinned| {
+                json!({
+                    "app_class": pinned.window.app_class,
+                    "title": pinned.window.title,
+                    "seconds": pinned.until.map(|until| {
+                        until.saturating_duration_since(Instant::now()).as_secs()
+                    }),
+                })
+            });
+            let now = Instant::now();
+            let pushed: Vec<Value> = notifier::pushed()
+                .into_iter()
+                .map(|pushed| {
+                    json!({
+                        "source": pushed.source,
+                        "app_class": pushed.window.app_class,
+                        "title": pushed.window.title,
+                        "priority": pushed.priority,
+                        "seconds": pushed.until.saturating_duration_since(now).as_secs(),
+                    })
+                })
+                .collect();
+            return json!({
+                "ok": true,
+                "version": env!("CARGO_PKG_VERSION"),
+                "pid": std::process::id(),
+                "platform": platform,
+                "paused": notifier::is_paused(),
+                "override": pinned,
+                "pushed": pushed,
+            });
+        }
+        Request::Current => {
+            let window = notifier::last_window().map(|window_info| {
+                json!({
+                    "app_class": window_info.app_class,
+                    "title": window_info.title,
+                    "fields": window_info.fields,
+                })
+            });
+            return json!({
+                "ok": true,
+                "window": window,
+                "message": notifier::last_message(),
+            });
+        }
+        Request::Pause => notifier::set_paused(true, verbose),
+        Request::Resume => notifier::set_paused(false, verbose),
+        Request::Toggle => {
+            return match notifier::toggle_paused(verbose) {
+                Ok(paused) => json!({ "ok": true, "paused": paused }),
+                Err(e) => error(e.to_string()),
+            }
+        }
+        Request::Reload => core::reload_runtime_config()
+            .map_err(|e| e.to_string().into())
+            .and_then(|()| notifier::refresh(verbose)),
+        Request::Send { message } => notifier::send_raw(message, verbose),
+        Request::Override {
+            app_class,
+            title,
+            seconds,
+        } => notifier::pin_context(
+            WindowInfo::new(app_class, title),
+            seconds.map(Duration::from_secs),
+            verbose,
+        ),
+        Request::ClearOverride => notifier::clear_pin(verbose),
+        Request::Subscribe => Err("Subscriptions need a connection of their own".into()),
+        Request::Push {
+            source,
+            app_class,
+            title,
+            fields,
+            priority,
+            seconds,
+        } => {
+            let mut window_info = WindowInfo::new(app_class, title);
+            window_info.fields = fields;
+            notifier::push_window(
+                source,
+                window_info,
+                priority,
+                Duration::from_secs(seconds.unwrap_or(DEFAULT_PUSH_SECONDS)),
+                verbose,
+            )
+        }
+        Request::ClearPush { source } => notifier::clear_push(&source, verbose),
+    };
+
+    match result {
+        Ok(()) => json!({ "ok": true }),
+        Err(e) => error(e.to_string()),
+    }
+}
+
+fn error(message: String) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+// Client side, used by `qmkonnect ctl`. Failed requests come back as errors.
+pub fn request(request: &Request) -> Result<Value, Box<dyn Error>> {
+    let path = socket_path()?;
+    let stream = UnixStream::connect(&path)
+        .map_err(|e| format!("QMKonnect is not running ({}: {})", path.display(), e))?;
+    request_on(stream, request)
+}
+
+fn request_on(stream: UnixStream, request: &Request) -> Result<Value, Box<dyn Error>> {
+    let (response, _) = exchange(stream, request)?;
+    Ok(response)
+}
+
+// Window events as JSON lines, for as long as the daemon runs
+pub fn subscribe() -> Result<impl Iterator<Item = std::io::Result<String>>, Box<dyn Error>> {
+    let path = socket_path()?;
+    let stream = UnixStream::connect(&path)
+        .map_err(|e| format!("QMKonnect is not running ({}: {})", path.display(), e))?;
+    subscribe_on(stream)
+}
+
+fn subscribe_on(
+    stream: UnixStream,
+) -> Result<impl Iterator<Item = std::io::Result<String>>, Box<dyn Error>> {
+    let (_, reader) = exchange(stream, &Request::Subscribe)?;
+    Ok(reader.lines())
+}
+
+// Send a request and read the response, leaving the rest of the stream for the caller
+fn exchange(
+    mut stream: UnixStream,
+    request: &Request,
+) -> Result<(Value, BufReader<UnixStream>), Box<dyn Error>> {
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let response: Value = serde_json::from_str(&line)?;
+    if response["ok"] != json!(true) {
+        let message = response["error"].as_str().unwrap_or("request failed");
+        return Err(message.into());
+    }
+    Ok((response, reader))
+}
+
+// Durations like "20m", "90s" or "1h30m"
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{}', use e.g. 20m, 90s or 1h30m", text);
+    let mut seconds = 0;
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        seconds += value * unit;
+        number.clear();
+    }
+
+    if !number.is_empty() || seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::notifier::Outcome;
+    use std::sync::mpsc;
+
+    fn connected() -> UnixStream {
+        subscribed(mpsc::sync_channel(0).1)
+    }
+
+    // Subscribers get the given events rather than the notifier's
+    fn subscribed(events: Receiver<WindowEvent>) -> UnixStream {
+        let (client, server) = UnixStream::pair().unwrap();
+        thread::spawn(move || serve(server, "Test", false, move || events));
+        client
+    }
+
+    #[test]
+    fn test_request_format() {
+        assert_eq!(
+            serde_json::to_string(&Request::Status).unwrap(),
+            r#"{"command":"status"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"send","message":"kitty"}"#).unwrap(),
+            Request::Send {
+                message: "kitty".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_override_format() {
+        assert_eq!(
+            serde_json::from_str::<Request>(
+                r#"{"command":"override","app_class":"TERMINAL","seconds":1200}"#
+            )
+            .unwrap(),
+            Request::Override {
+                app_class: "TERMINAL".to_string(),
+                title: String::new(),
+                seconds: Some(1200),
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&Request::ClearOverride).unwrap(),
+            r#"{"command":"clear-override"}"#
+        );
+    }
+
+    #[test]
+    fn test_push_format() {
+        let request: Request =
+            serde_json::from_str(r#"{"command":"push","app_class":"VM","priority":2}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Push {
+                source: "push".to_string(),
+                app_class: "VM".to_string(),
+                title: String::new(),
+                fields: BTreeMap::new(),
+                priority: 2,
+                seconds: None,
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"clear-push","source":"vm"}"#).unwrap(),
+            Request::ClearPush {
+                source: "vm".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("20m"), Ok(Duration::from_secs(1200)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("20").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("2d").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn test_status() {
+        let response = request_on(connected(), &Request::Status).unwrap();
+        assert_eq!(response["platform"], "Test");
+        assert_eq!(response["pid"], std::process::id());
+        assert!(response["paused"].is_boolean());
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let mut stream = connected();
+        writeln!(stream, r#"{{"command":"explode"}}"#).unwrap();
+        writeln!(stream, "not json").unwrap();
+
+        let mut lines = BufReader::new(stream).lines();
+        for _ in 0..2 {
+            let response: Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+            assert_eq!(response["ok"], false);
+            assert!(response["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid request"));
+        }
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let (sender, events) = mpsc::sync_channel(1);
+        let mut lines = subscribe_on(subscribed(events)).unwrap();
+
+        let window_info = WindowInfo::new("SubscribedApp".to_string(), "Notes".to_string());
+        sender
+            .send(WindowEvent {
+                window: window_info.clone(),
+                source: None,
+                processed: Some(window_info),
+                message: Some("SubscribedApp\x1DNotes".to_string()),
+                outcome: Outcome::Sent,
+            })
+            .unwrap();
+        let event: Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(event["window"]["app_class"], "SubscribedApp");
+        assert_eq!(event["processed"]["title"], "Notes");
+        assert_eq!(event["outcome"], "sent");
+
+        // The stream ends with the notifier
+        drop(sender);
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn test_refuses_running_daemon() {
+        let path =
+            std::env::temp_dir().join(format!("qmkonnect-control-{}.sock", std::process::id()));
+        let _listener = bind(&path).unwrap();
+        let error = bind(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+        let _ = fs::remove_file(path);
+    }
+}
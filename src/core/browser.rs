@@ -0,0 +1,424 @@
+// Active tab context from the browser extension in packaging/browser. The
+// browser starts `qmkonnect native-host`, which passes each tab on to the
+// running daemon over a local socket as one JSON object per line.
+use crate::core::pipeline::matches_pattern;
+use crate::core::types::WindowInfo;
+use once_cell::sync::Lazy;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+
+// Browsers allow 1 MB from the host, and a tab is a lot less than that the other way
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+// [browser] section
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(default)]
+pub struct BrowserConfig {
+    // Class patterns of browsers with the extension, e.g. "firefox" or "*chrom*"
+    pub classes: Vec<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Tab {
+    pub url: String,
+    // Derived from the URL when the extension leaves it out
+    #[serde(default)]
+    pub domain: String,
+}
+
+impl Tab {
+    fn with_domain(mut self) -> Self {
+        if self.domain.is_empty() {
+            self.domain = domain_of(&self.url).to_string();
+        }
+        self
+    }
+}
+
+// Tab last reported by the extension, and the connection it came in on.
+// None while no browser is connected.
+static ACTIVE_TAB: Lazy<Mutex<Option<(u64, Tab)>>> = Lazy::new(|| Mutex::new(None));
+
+// Add the active tab's domain and URL to the window, if it is a browser
+pub fn enrich(window_info: &WindowInfo, config: &BrowserConfig) -> WindowInfo {
+    let mut window_info = window_info.clone();
+    if !is_browser(&window_info, config) {
+        return window_info;
+    }
+
+    if let Some((_, tab)) = ACTIVE_TAB.lock().unwrap().clone() {
+        window_info.fields.insert("domain".to_string(), tab.domain);
+        window_info.fields.insert("url".to_string(), tab.url);
+    }
+    window_info
+}
+
+fn is_browser(window_info: &WindowInfo, config: &BrowserConfig) -> bool {
+    config
+        .classes
+        .iter()
+        .any(|pattern| matches_pattern(pattern, &window_info.app_class))
+}
+
+// Host of a URL, without credentials or port
+fn domain_of(url: &str) -> &str {
+    let Some((_, rest)) = url.split_once("://") else {
+        return "";
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit('@').next().unwrap_or("");
+    if host.starts_with('[') {
+        // IPv6 literal, which has colons of its own
+        return host.split_inclusive(']').next().unwrap_or(host);
+    }
+    host.split(':').next().unwrap_or(host)
+}
+
+// Native messages are a length in native byte order followed by that much JSON.
+// Returns None once the browser closes the stream between messages.
+pub fn read_message(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match reader.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let length = u32::from_ne_bytes(length) as usize;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is too large", length),
+        ));
+    }
+
+    let mut message = vec![0u8; length];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    if message.len() > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is too large", message.len()),
+        ));
+    }
+
+    writer.write_all(&(message.len() as u32).to_ne_bytes())?;
+    writer.write_all(message)?;
+    writer.flush()
+}
+
+#[cfg(unix)]
+pub use self::host::{run_native_host, start};
+
+#[cfg(unix)]
+mod host {
+    use super::*;
+    use crate::core::notifier;
+    use std::error::Error;
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::thread;
+
+    fn socket_path() -> Result<PathBuf, Box<dyn Error>> {
+        Ok(crate::core::runtime_dir()?.join("browser.sock"))
+    }
+
+    // Runs as the browser's native messaging host. Stdout belongs to the
+    // browser, so anything for the user goes to stderr.
+    pub fn run_native_host() -> Result<(), Box<dyn Error>> {
+        let path = socket_path()?;
+        let mut daemon: Option<UnixStream> = None;
+        let mut stdin = io::stdin().lock();
+        let mut stdout = io::stdout().lock();
+
+        while let Some(message) = read_message(&mut stdin)? {
+            let tab = match serde_json::from_slice::<Tab>(&message) {
+                Ok(tab) => tab.with_domain(),
+                Err(e) => {
+                    eprintln!("Ignoring message from the browser: {}", e);
+                    continue;
+                }
+            };
+            let mut line = serde_json::to_string(&tab)?;
+            line.push('\n');
+
+            // The daemon may have been started or restarted since the last tab
+            if daemon.is_none() {
+                daemon = UnixStream::connect(&path).ok();
+            }
+            if let Some(stream) = daemon.as_mut() {
+                if stream.write_all(line.as_bytes()).is_err() {
+                    daemon = None;
+                }
+            }
+
+            // Lets the extension tell whether QMKonnect is listening
+            let reply = serde_json::json!({ "connected": daemon.is_some() });
+            write_message(&mut stdout, reply.to_string().as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    // Listen for native hosts in the background if any browser is configured
+    pub fn start(config: &BrowserConfig, verbose: bool) {
+        if config.classes.is_empty() {
+            return;
+        }
+
+        let listener = match socket_path().and_then(|path| {
+            // Left behind by a previous run
+            let _ = fs::remove_file(&path);
+            Ok(UnixListener::bind(&path)?)
+        }) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Could not listen for browser tabs: {}", e);
+                return;
+            }
+        };
+
+        thread::spawn(move || {
+            // Each browser profile starts a host of its own
+            for (connection, stream) in (0..).zip(listener.incoming().flatten()) {
+                thread::spawn(move || read_tabs(stream, connection, verbose));
+            }
+        });
+    }
+
+    pub(super) fn read_tabs(stream: UnixStream, connection: u64, verbose: bool) {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Ok(tab) = serde_json::from_str::<Tab>(&line) {
+                update(connection, Some(tab.with_domain()), verbose);
+            }
+        }
+
+        // The browser closed the extension's port, and with it the host
+        update(connection, None, verbose);
+    }
+
+    fn update(connection: u64, tab: Option<Tab>, verbose: bool) {
+        {
+            let mut active = ACTIVE_TAB.lock().unwrap();
+            let previous = match &tab {
+                Some(tab) => active.replace((connection, tab.clone())),
+                // Another browser's tab is still current
+                None if active.as_ref().is_some_and(|(from, _)| *from != connection) => return,
+                None => active.take(),
+            };
+            if previous.map(|(_, tab)| tab) == tab {
+                return;
+            }
+        }
+
+        if verbose {
+            match &tab {
+                Some(tab) => println!("Active tab is on '{}'", tab.domain),
+                None => println!("Browser disconnected"),
+            }
+        }
+
+        // Switching tabs doesn't always change the focused window's title
+        let browser = crate::core::runtime_config().browser;
+        let Some(window_info) = notifier::last_window() else {
+            return;
+        };
+        if is_browser(&window_info, &browser) {
+            if let Err(e) = notifier::refresh(verbose) {
+                eprintln!("Error notifying QMK: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Tests that set the active tab
+    static ACTIVE_TAB_LOCK: Mutex<()> = Mutex::new(());
+
+    fn framed(message: &[u8]) -> Vec<u8> {
+        let mut bytes = (message.len() as u32).to_ne_bytes().to_vec();
+        bytes.extend_from_slice(message);
+        bytes
+    }
+
+    #[test]
+    fn test_read_messages() {
+        let mut bytes = framed(br#"{"url":"https://github.com/"}"#);
+        bytes.extend(framed(b"{}"));
+        let mut reader = Cursor::new(bytes);
+
+        assert_eq!(
+            read_message(&mut reader).unwrap().unwrap(),
+            br#"{"url":"https://github.com/"}"#
+        );
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), b"{}");
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_empty_message() {
+        let mut reader = Cursor::new(framed(b""));
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), b"");
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_truncated_message() {
+        // Length promises more than the browser sent
+        let mut bytes = framed(br#"{"url":"https://github.com/"}"#);
+        bytes.truncate(10);
+        let error = read_message(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Half a length at the end of the stream
+        let error = read_message(&mut Cursor::new(vec![0x10, 0x00]));
+        assert_eq!(error.unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_oversized_message() {
+        let bytes = ((MAX_MESSAGE_SIZE + 1) as u32).to_ne_bytes().to_vec();
+        let error = read_message(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_write_message() {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, br#"{"ok":true}"#).unwrap();
+        assert_eq!(bytes, framed(br#"{"ok":true}"#));
+
+        // What we write reads back the same
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(
+            read_message(&mut reader).unwrap().unwrap(),
+            br#"{"ok":true}"#
+        );
+
+        let oversized = vec![b' '; MAX_MESSAGE_SIZE + 1];
+        let error = write_message(&mut Vec::new(), &oversized).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_domain_of() {
+        assert_eq!(domain_of("https://claude.ai/chat/123"), "claude.ai");
+        assert_eq!(domain_of("http://user:pw@localhost:8080/?q=1"), "localhost");
+        assert_eq!(domain_of("https://github.com?tab=repos"), "github.com");
+        assert_eq!(domain_of("http://[::1]:3000/"), "[::1]");
+        assert_eq!(domain_of("about:blank"), "");
+    }
+
+    #[test]
+    fn test_tab_message() {
+        let tab: Tab = serde_json::from_str(r#"{"url":"https://docs.rs/serde"}"#).unwrap();
+        assert_eq!(tab.with_domain().domain, "docs.rs");
+
+        // The extension's domain wins
+        let tab: Tab =
+            serde_json::from_str(r#"{"url":"https://docs.rs/","domain":"rust"}"#).unwrap();
+        assert_eq!(tab.with_domain().domain, "rust");
+    }
+
+    #[test]
+    fn test_packaging_matches_host() {
+        let extension = include_str!("../../packaging/browser/extension/background.js");
+        let firefox = include_str!("../../packaging/browser/firefox.json");
+        let chrome = include_str!("../../packaging/browser/chrome.json");
+
+        assert!(extension.contains("'io.github.qmkonnect'"));
+        assert!(firefox.contains("\"io.github.qmkonnect\""));
+        assert!(chrome.contains("\"io.github.qmkonnect\""));
+    }
+
+    #[test]
+    fn test_enrich_browsers_only() {
+        let _lock = ACTIVE_TAB_LOCK.lock().unwrap();
+        let config = BrowserConfig {
+            classes: vec!["firefox".to_string()],
+        };
+        *ACTIVE_TAB.lock().unwrap() = Some((
+            0,
+            Tab {
+                url: "https://claude.ai/new".to_string(),
+                domain: String::new(),
+            }
+            .with_domain(),
+        ));
+
+        let firefox = WindowInfo::new("firefox".to_string(), "New Tab".to_string());
+        let window_info = enrich(&firefox, &config);
+        assert_eq!(window_info.fields["domain"], "claude.ai");
+        assert_eq!(window_info.fields["url"], "https://claude.ai/new");
+
+        let kitty = WindowInfo::new("kitty".to_string(), "zsh".to_string());
+        assert_eq!(enrich(&kitty, &config), kitty);
+
+        *ACTIVE_TAB.lock().unwrap() = None;
+        assert_eq!(enrich(&firefox, &config), firefox);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tab_per_connection() {
+        use std::os::unix::net::UnixStream;
+        use std::thread;
+        use std::time::Duration;
+
+        let _lock = ACTIVE_TAB_LOCK.lock().unwrap();
+        *ACTIVE_TAB.lock().unwrap() = None;
+
+        let active_domain = || {
+            ACTIVE_TAB
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|(_, tab)| tab.domain.clone())
+        };
+        let wait_for = |domain: &str| {
+            for _ in 0..200 {
+                if active_domain().as_deref() == Some(domain) {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("active tab never moved to {}", domain);
+        };
+
+        // Two browsers, each with a host of its own
+        let (mut firefox, host) = UnixStream::pair().unwrap();
+        let firefox_host = thread::spawn(move || host::read_tabs(host, 1, false));
+        let (mut chrome, host) = UnixStream::pair().unwrap();
+        let chrome_host = thread::spawn(move || host::read_tabs(host, 2, false));
+
+        firefox
+            .write_all(b"{\"url\":\"https://docs.rs/\"}\n")
+            .unwrap();
+        wait_for("docs.rs");
+        chrome
+            .write_all(b"{\"url\":\"https://github.com/\"}\n")
+            .unwrap();
+        wait_for("github.com");
+
+        // Closing the browser whose tab isn't current changes nothing
+        drop(firefox);
+        firefox_host.join().unwrap();
+        assert_eq!(active_domain().as_deref(), Some("github.com"));
+
+        drop(chrome);
+        chrome_host.join().unwrap();
+        assert_eq!(active_domain(), None);
+    }
+}
@@ -0,0 +1,176 @@
+// Editor mode updates, sent by the Neovim snippet in packaging/neovim over a
+// local socket as one JSON object per line, e.g. {"mode":"i"}
+use crate::core::notifier;
+use crate::core::types::HostEvent;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+#[derive(serde::Deserialize)]
+struct ModeMessage {
+    mode: String,
+}
+
+pub fn socket_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(crate::core::runtime_dir()?.join("nvim.sock"))
+}
+
+// Listen for editors in the background if mode messages are enabled
+pub fn start(verbose: bool) {
+    if !crate::core::runtime_config().encoder.mode {
+        return;
+    }
+
+    let listener = match socket_path().and_then(|path| Ok(bind(&path)?)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not listen for editor modes: {}", e);
+            return;
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || serve(listener, sender));
+    thread::spawn(move || {
+        for mode in receiver {
+            if verbose {
+                println!("Editor mode changed to '{}'", mode);
+            }
+            if let Err(e) = notifier::notify_event(&HostEvent::Mode(mode), verbose) {
+                eprintln!("Error notifying QMK: {}", e);
+            }
+        }
+    });
+}
+
+fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    // Left behind by a previous run
+    let _ = fs::remove_file(path);
+    UnixListener::bind(path)
+}
+
+// Every editor instance keeps its own connection open
+fn serve(listener: UnixListener, sender: Sender<String>) {
+    for stream in listener.incoming().flatten() {
+        let sender = sender.clone();
+        thread::spawn(move || read_modes(stream, sender));
+    }
+}
+
+fn read_modes(stream: UnixStream, sender: Sender<String>) {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        // Ignore what we don't understand rather than dropping the editor
+        if let Ok(message) = serde_json::from_str::<ModeMessage>(&line) {
+            if sender.send(mode_name(&message.mode).to_string()).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Neovim reports modes as short codes, e.g. "niI" or "\x16" for blockwise
+// visual. Firmware only needs to know the kind of mode. An empty mode means
+// the editor was closed.
+fn mode_name(mode: &str) -> &str {
+    match mode.chars().next() {
+        Some('n') => "normal",
+        Some('i') => "insert",
+        Some('v' | 'V' | '\x16') => "visual",
+        Some('s' | 'S' | '\x13') => "select",
+        Some('R') => "replace",
+        Some('c') => "command",
+        Some('t') => "terminal",
+        _ => mode,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    const SNIPPET: &str = include_str!("../../packaging/neovim/qmkonnect.lua");
+
+    fn listen(name: &str) -> (PathBuf, mpsc::Receiver<String>) {
+        let path =
+            std::env::temp_dir().join(format!("qmkonnect-{}-{}.sock", name, std::process::id()));
+        let listener = bind(&path).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || serve(listener, sender));
+        (path, receiver)
+    }
+
+    #[test]
+    fn test_mode_name() {
+        assert_eq!(mode_name("n"), "normal");
+        assert_eq!(mode_name("niI"), "normal");
+        assert_eq!(mode_name("i"), "insert");
+        assert_eq!(mode_name("\x16"), "visual");
+        assert_eq!(mode_name("Rv"), "replace");
+        assert_eq!(mode_name("r?"), "r?");
+        assert_eq!(mode_name(""), "");
+    }
+
+    #[test]
+    fn test_reads_modes_per_line() {
+        let (path, receiver) = listen("modes");
+
+        let mut editor = UnixStream::connect(&path).unwrap();
+        editor
+            .write_all(b"{\"mode\":\"i\"}\nnot json\n{\"mode\":\"V\"}\n{\"mode\":\"\"}\n")
+            .unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "insert");
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "visual");
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "");
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_neovim_snippet() {
+        let (path, receiver) = listen("nvim");
+        let snippet = path.with_extension("lua");
+        fs::write(&snippet, SNIPPET).unwrap();
+
+        let status = Command::new("nvim")
+            .args(["--headless", "--clean", "--cmd"])
+            .arg(format!("let g:qmkonnect_socket = '{}'", path.display()))
+            .arg("-S")
+            .arg(&snippet)
+            .args(["-c", "call feedkeys(\"ihello\\<Esc>\", 'x')", "-c", "qa!"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        let _ = fs::remove_file(&snippet);
+        if status.is_err() {
+            eprintln!("nvim not available, skipping");
+            return;
+        }
+
+        let mut modes = Vec::new();
+        while let Ok(mode) = receiver.recv_timeout(Duration::from_secs(5)) {
+            let closed = mode.is_empty();
+            modes.push(mode);
+            if closed {
+                break;
+            }
+        }
+        let typed = modes.iter().position(|mode| mode == "insert").unwrap();
+        assert_eq!(modes[typed + 1], "normal");
+        assert_eq!(modes.last().unwrap(), "");
+
+        let _ = fs::remove_file(path);
+    }
+}
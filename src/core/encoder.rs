@@ -0,0 +1,201 @@
+use crate::core::types::{HostEvent, WindowInfo};
+use std::collections::HashMap;
+
+// Separates the fields of a message, e.g. app class and title
+pub const FIELD_SEPARATOR: char = '\x1D';
+
+// Leads every message that isn't window context, so firmware can tell them apart
+pub const EVENT_MARKER: char = '\x1E';
+
+// How Hyprland submaps reach the keyboard
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubmapMode {
+    // Not sent at all
+    #[default]
+    Off,
+    // Sent as their own message, window context keeps flowing
+    Alongside,
+    // Sent as their own message, window context is held back until the submap is left
+    Instead,
+}
+
+// [encoder] section
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(default)]
+pub struct EncoderConfig {
+    pub submap: SubmapMode,
+    // Send keyboard layout switches
+    pub layout: bool,
+    // Short codes to send instead of layout names, e.g. "German" = "de".
+    // Unmapped layouts are sent by name.
+    pub layout_codes: HashMap<String, String>,
+    // Send the class of windows that ask for attention
+    pub attention: bool,
+    // Send fullscreen transitions of the focused window
+    pub fullscreen: bool,
+    // Send editor mode changes reported by the Neovim snippet
+    pub mode: bool,
+    // Window fields appended to class and title, e.g. ["workspace"]
+    pub fields: Vec<String>,
+}
+
+impl EncoderConfig {
+    // Whether the event should be sent to the keyboard at all
+    pub fn forwards(&self, event: &HostEvent) -> bool {
+        match event {
+            HostEvent::Submap(_) => self.submap != SubmapMode::Off,
+            HostEvent::Layout(_) => self.layout,
+            HostEvent::Attention(_) => self.attention,
+            HostEvent::Fullscreen(_) => self.fullscreen,
+            HostEvent::Mode(_) => self.mode,
+        }
+    }
+
+    // Class and title, then each configured field in order. Fields the window
+    // doesn't have are sent empty so positions stay fixed.
+    pub fn encode_window(&self, window_info: &WindowInfo) -> String {
+        let mut message = format!(
+            "{}{}{}",
+            window_info.app_class, FIELD_SEPARATOR, window_info.title
+        );
+        for field in &self.fields {
+            message.push(FIELD_SEPARATOR);
+            message.push_str(window_info.fields.get(field).map_or("", String::as_str));
+        }
+        message
+    }
+
+    // Events are encoded as marker, tag, separator, value
+    pub fn encode_event(&self, event: &HostEvent) -> String {
+        let (tag, value) = match event {
+            HostEvent::Submap(name) => ("SUBMAP", name.as_str()),
+            HostEvent::Layout(name) => (
+                "LAYOUT",
+                self.layout_codes.get(name).unwrap_or(name).as_str(),
+            ),
+            HostEvent::Attention(app_class) => ("ATTENTION", app_class.as_str()),
+            HostEvent::Fullscreen(fullscreen) => {
+                ("FULLSCREEN", if *fullscreen { "1" } else { "0" })
+            }
+            HostEvent::Mode(mode) => ("MODE", mode.as_str()),
+        };
+        format!("{}{}{}{}", EVENT_MARKER, tag, FIELD_SEPARATOR, value)
+    }
+}
+
+// Make a message readable in logs
+pub fn sanitize(message: &str) -> String {
+    message
+        .replace(FIELD_SEPARATOR, "|")
+        .replace(EVENT_MARKER, "!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_window() {
+        let window_info = WindowInfo::new("kitty".to_string(), "nvim".to_string());
+        assert_eq!(
+            EncoderConfig::default().encode_window(&window_info),
+            "kitty\x1Dnvim"
+        );
+    }
+
+    #[test]
+    fn test_encode_window_fields() {
+        let config: EncoderConfig = toml::from_str(r#"fields = ["missing", "workspace"]"#).unwrap();
+        let mut window_info = WindowInfo::new("kitty".to_string(), "nvim".to_string());
+        window_info
+            .fields
+            .insert("workspace".to_string(), "2".to_string());
+
+        assert_eq!(config.encode_window(&window_info), "kitty\x1Dnvim\x1D\x1D2");
+    }
+
+    #[test]
+    fn test_encode_submap() {
+        let config = EncoderConfig::default();
+        assert_eq!(
+            config.encode_event(&HostEvent::Submap("resize".to_string())),
+            "\x1ESUBMAP\x1Dresize"
+        );
+        // Leaving a submap is an empty name
+        assert_eq!(
+            config.encode_event(&HostEvent::Submap(String::new())),
+            "\x1ESUBMAP\x1D"
+        );
+    }
+
+    #[test]
+    fn test_submap_mode_config() {
+        let config: EncoderConfig = toml::from_str("submap = \"instead\"").unwrap();
+        assert_eq!(config.submap, SubmapMode::Instead);
+        assert!(config.forwards(&HostEvent::Submap("resize".to_string())));
+
+        let config: EncoderConfig = toml::from_str("").unwrap();
+        assert_eq!(config.submap, SubmapMode::Off);
+        assert!(!config.forwards(&HostEvent::Submap("resize".to_string())));
+    }
+
+    #[test]
+    fn test_layout_codes() {
+        let config: EncoderConfig = toml::from_str(
+            r#"
+            layout = true
+
+            [layout_codes]
+            "English (US)" = "us"
+            "German" = "de"
+            "#,
+        )
+        .unwrap();
+
+        let german = HostEvent::Layout("German".to_string());
+        assert!(config.forwards(&german));
+        assert_eq!(config.encode_event(&german), "\x1ELAYOUT\x1Dde");
+        assert_eq!(
+            config.encode_event(&HostEvent::Layout("French".to_string())),
+            "\x1ELAYOUT\x1DFrench"
+        );
+
+        assert!(!EncoderConfig::default().forwards(&german));
+    }
+
+    #[test]
+    fn test_encode_attention() {
+        let config: EncoderConfig = toml::from_str("attention = true").unwrap();
+        let event = HostEvent::Attention("Slack".to_string());
+        assert!(config.forwards(&event));
+        assert_eq!(config.encode_event(&event), "\x1EATTENTION\x1DSlack");
+    }
+
+    #[test]
+    fn test_encode_fullscreen() {
+        let config = EncoderConfig::default();
+        assert_eq!(
+            config.encode_event(&HostEvent::Fullscreen(true)),
+            "\x1EFULLSCREEN\x1D1"
+        );
+        assert_eq!(
+            config.encode_event(&HostEvent::Fullscreen(false)),
+            "\x1EFULLSCREEN\x1D0"
+        );
+    }
+
+    #[test]
+    fn test_encode_mode() {
+        let config: EncoderConfig = toml::from_str("mode = true").unwrap();
+        let event = HostEvent::Mode("insert".to_string());
+        assert!(config.forwards(&event));
+        assert_eq!(config.encode_event(&event), "\x1EMODE\x1Dinsert");
+        assert!(!EncoderConfig::default().forwards(&event));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("\x1ESUBMAP\x1Dresize"), "!SUBMAP|resize");
+    }
+}
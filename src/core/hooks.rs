@@ -0,0 +1,358 @@
+// User commands run when the keyboard's context changes, e.g. to switch the
+// input method for a chat app. They follow the notifier's event stream on a
+// thread of their own, so a slow hook never holds up the keyboard.
+use crate::core::notifier::{self, Outcome, WindowEvent};
+use crate::core::pipeline::matches_pattern;
+use crate::core::types::WindowInfo;
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Hooks allowed to run at once, any more are skipped
+const MAX_RUNNING: usize = 4;
+
+// How often a running hook is checked for having finished
+const WAIT_INTERVAL: Duration = Duration::from_millis(20);
+
+// Commands of the hooks running now. A hook doesn't start again before it is done.
+static RUNNING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+// [[hooks]] entries. Every rule that is set must match.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Hook {
+    // Class pattern, e.g. "firefox" or "*term*"
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    // Field patterns, e.g. domain = "*.slack.com"
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    // Run with sh -c, or cmd /C on Windows
+    pub command: String,
+    // Killed when it takes any longer
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+impl Hook {
+    fn matches(&self, window_info: &WindowInfo) -> bool {
+        self.class
+            .as_ref()
+            .is_none_or(|pattern| matches_pattern(pattern, &window_info.app_class))
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|pattern| matches_pattern(pattern, &window_info.title))
+            && self.fields.iter().all(|(name, pattern)| {
+                window_info
+                    .fields
+                    .get(name)
+                    .is_some_and(|value| matches_pattern(pattern, value))
+            })
+    }
+
+    // Hooks run once when their rules start matching, not on every title change
+    fn entered(&self, previous: Option<&WindowInfo>, current: &WindowInfo) -> bool {
+        self.matches(current) && !previous.is_some_and(|previous| self.matches(previous))
+    }
+}
+
+// Run hooks for what the keyboard is sent from now on. They are read from the
+// configuration every time, so reloading picks up changes.
+pub fn start(verbose: bool) {
+    let events = notifier::subscribe();
+    thread::spawn(move || {
+        let mut previous: Option<WindowInfo> = None;
+        for event in &events {
+            // Only the latest of a burst of focus changes counts
+            let Some(current) = std::iter::once(event)
+                .chain(events.try_iter())
+                .filter_map(context)
+                .last()
+            else {
+                continue;
+            };
+
+            for hook in &crate::core::runtime_config().hooks {
+                if hook.entered(previous.as_ref(), &current) {
+                    run(hook, &current, verbose);
+                }
+            }
+            previous = Some(current);
+        }
+    });
+}
+
+// The context the keyboard gets from the event, if any
+fn context(event: WindowEvent) -> Option<WindowInfo> {
+    match event.outcome {
+        Outcome::Sent | Outcome::Debounced | Outcome::Pinned => event.processed,
+        Outcome::Suppressed | Outcome::Held | Outcome::Paused => None,
+    }
+}
+
+// Start the hook unless it is still running or too many others are. Returns
+// the thread waiting for it.
+fn run(hook: &Hook, window_info: &WindowInfo, verbose: bool) -> Option<JoinHandle<()>> {
+    {
+        let mut running = RUNNING.lock().unwrap();
+        if running.contains(&hook.command) || running.len() >= MAX_RUNNING {
+            if verbose {
+                println!("Skipping hook, still busy: {}", hook.command);
+            }
+            return None;
+        }
+        running.insert(hook.command.clone());
+    }
+
+    let command = hook.command.clone();
+    let timeout = Duration::from_millis(hook.timeout_ms);
+    // Hook output only shows up when verbose
+    let output = || {
+        if verbose {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        }
+    };
+    let spawned = shell(&command)
+        .envs(environment(window_info))
+        .stdin(Stdio::null())
+        .stdout(output())
+        .stderr(output())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Could not run hook '{}': {}", command, e);
+            RUNNING.lock().unwrap().remove(&command);
+            return None;
+        }
+    };
+    if verbose {
+        println!("Running hook: {}", command);
+    }
+
+    Some(thread::spawn(move || {
+        match wait(&mut child, timeout) {
+            Ok(true) => {}
+            Ok(false) => eprintln!("Hook timed out after {:?}: {}", timeout, command),
+            Err(e) => eprintln!("Error waiting for hook '{}': {}", command, e),
+        }
+        RUNNING.lock().unwrap().remove(&command);
+    }))
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    use std::os::unix::process::CommandExt;
+
+    let mut shell = Command::new("sh");
+    // Its own process group, so a timeout takes down whatever it started too
+    shell.arg("-c").arg(command).process_group(0);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+// QMKONNECT_CLASS, QMKONNECT_TITLE and QMKONNECT_FIELD_<NAME> for every field
+fn environment(window_info: &WindowInfo) -> Vec<(String, String)> {
+    let mut environment = vec![
+        ("QMKONNECT_CLASS".to_string(), window_info.app_class.clone()),
+        ("QMKONNECT_TITLE".to_string(), window_info.title.clone()),
+    ];
+    for (name, value) in &window_info.fields {
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        environment.push((format!("QMKONNECT_FIELD_{}", name), value.clone()));
+    }
+    environment
+}
+
+// Returns whether the hook finished in time, it is killed otherwise
+fn wait(child: &mut Child, timeout: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if child.try_wait()?.is_some() {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            kill(child);
+            child.wait()?;
+            return Ok(false);
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    let killed = unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) } == 0;
+    if !killed {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(windows)]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn hook(command: &str) -> Hook {
+        toml::from_str(&format!("command = '{}'", command)).unwrap()
+    }
+
+    fn window(app_class: &str, title: &str) -> WindowInfo {
+        WindowInfo::new(app_class.to_string(), title.to_string())
+    }
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("qmkonnect-hook-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_config() {
+        let hooks: BTreeMap<String, Vec<Hook>> = toml::from_str(
+            r#"
+            [[hooks]]
+            class = "slack"
+            command = "slack-status busy"
+
+            [[hooks]]
+            fields = { domain = "*.slack.com" }
+            command = "slack-status busy"
+            timeout_ms = 500
+            "#,
+        )
+        .unwrap();
+        let hooks = &hooks["hooks"];
+        assert_eq!(hooks[0].timeout_ms, 10_000);
+        assert_eq!(hooks[1].timeout_ms, 500);
+        assert!(hooks[1].class.is_none());
+    }
+
+    #[test]
+    fn test_matches() {
+        // Without rules, every window matches
+        assert!(hook("true").matches(&window("firefox", "")));
+
+        let mut hook = hook("true");
+        hook.class = Some("*term*".to_string());
+        hook.title = Some("*vim*".to_string());
+        hook.fields.insert("host".to_string(), "prod-*".to_string());
+
+        let mut window_info = window("xterm", "nvim main.rs");
+        assert!(!hook.matches(&window_info));
+        window_info
+            .fields
+            .insert("host".to_string(), "prod-db".to_string());
+        assert!(hook.matches(&window_info));
+        assert!(!hook.matches(&window("xterm", "zsh")));
+    }
+
+    #[test]
+    fn test_runs_on_entering() {
+        let mut hook = hook("true");
+        hook.class = Some("slack".to_string());
+        let slack = window("Slack", "general");
+
+        assert!(hook.entered(None, &slack));
+        assert!(hook.entered(Some(&window("firefox", "")), &slack));
+        // Still in Slack, only the title changed
+        assert!(!hook.entered(Some(&slack), &window("Slack", "random")));
+        assert!(!hook.entered(Some(&slack), &window("firefox", "")));
+    }
+
+    #[test]
+    fn test_context() {
+        let event = |outcome| WindowEvent {
+            window: window("kitty", "zsh"),
+            source: None,
+            processed: Some(window("TERMINAL", "zsh")),
+            message: None,
+            outcome,
+        };
+        assert_eq!(
+            context(event(Outcome::Sent)),
+            Some(window("TERMINAL", "zsh"))
+        );
+        assert_eq!(
+            context(event(Outcome::Debounced)),
+            Some(window("TERMINAL", "zsh"))
+        );
+        assert_eq!(
+            context(event(Outcome::Pinned)),
+            Some(window("TERMINAL", "zsh"))
+        );
+        assert_eq!(context(event(Outcome::Paused)), None);
+    }
+
+    #[test]
+    fn test_environment() {
+        let output = temp_file("env");
+        let hook = hook(&format!(
+            r#"printf "%s|%s|%s" "$QMKONNECT_CLASS" "$QMKONNECT_TITLE" "$QMKONNECT_FIELD_TMUX_COMMAND" > {}"#,
+            output.display()
+        ));
+        let mut window_info = window("kitty", "it's \"quoted\"");
+        window_info
+            .fields
+            .insert("tmux_command".to_string(), "nvim".to_string());
+
+        run(&hook, &window_info, false).unwrap().join().unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "kitty|it's \"quoted\"|nvim"
+        );
+        let _ = fs::remove_file(output);
+    }
+
+    #[test]
+    fn test_timeout() {
+        // Whatever the hook started in the background has to go as well
+        let output = temp_file("timeout");
+        let mut hook = hook(&format!("(sleep 1; touch {}) & wait", output.display()));
+        hook.timeout_ms = 100;
+
+        let started = Instant::now();
+        let waiting = run(&hook, &window("kitty", ""), false).unwrap();
+        // Busy until the first run is over
+        assert!(run(&hook, &window("kitty", ""), false).is_none());
+        waiting.join().unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!output.exists());
+        let waiting = run(&hook, &window("kitty", ""), false);
+        assert!(waiting.is_some());
+        waiting.unwrap().join().unwrap();
+        let _ = fs::remove_file(output);
+    }
+}
@@ -1,20 +1,94 @@
+pub mod browser;
 pub mod config;
+#[cfg(unix)]
+pub mod editor;
+pub mod encoder;
+pub mod hooks;
+pub mod mqtt;
 pub mod notifier;
+pub mod pipeline;
+pub mod remote;
+pub mod terminal;
+pub mod title;
+pub mod tmux;
 pub mod types;
+pub mod webhook;
 
+use once_cell::sync::Lazy;
 use std::error::Error;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 
 // Define the Config struct
-#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
 pub struct Config {
     // Use u16 for the IDs
     pub vendor_id: u16,
     pub product_id: u16,
-    // Add any other fields your config needs
+    // Optional sections, all of which fall back to defaults when missing
+    #[serde(default)]
+    pub hyprland: HyprlandConfig,
+    #[serde(default)]
+    pub encoder: encoder::EncoderConfig,
+    #[serde(default)]
+    pub gaming: pipeline::GamingConfig,
+    #[serde(default)]
+    pub workspaces: Vec<pipeline::WorkspaceRule>,
+    #[serde(default)]
+    pub sticky: pipeline::StickyConfig,
+    #[serde(default)]
+    pub terminal: terminal::TerminalConfig,
+    #[serde(default)]
+    pub browser: browser::BrowserConfig,
+    #[serde(default)]
+    pub titles: title::TitleConfig,
+    #[serde(default)]
+    pub remote: remote::RemoteConfig,
+    #[serde(default)]
+    pub hooks: Vec<hooks::Hook>,
+    #[serde(default)]
+    pub mqtt: mqtt::MqttConfig,
+    #[serde(default)]
+    pub webhooks: Vec<webhook::Webhook>,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            vendor_id: 0xfeed,
+            product_id: 0x0000,
+            hyprland: HyprlandConfig::default(),
+            encoder: encoder::EncoderConfig::default(),
+            gaming: pipeline::GamingConfig::default(),
+            workspaces: Vec::new(),
+            sticky: pipeline::StickyConfig::default(),
+            terminal: terminal::TerminalConfig::default(),
+            browser: browser::BrowserConfig::default(),
+            titles: title::TitleConfig::default(),
+            remote: remote::RemoteConfig::default(),
+            hooks: Vec::new(),
+            mqtt: mqtt::MqttConfig::default(),
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+// [hyprland] section
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(default)]
+pub struct HyprlandConfig {
+    // Instance signature to follow. By default the one we were started under,
+    // or the newest running instance once that one is gone.
+    pub instance: Option<String>,
+    // Also poll the active window every N milliseconds. Off unless set, events
+    // cover every focus change we know of.
+    pub poll_interval_ms: Option<u64>,
+}
+
+// Configuration the running notifier works from
+static RUNTIME_CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| RwLock::new(Config::default()));
+
 pub fn parse_config(config_path: &Path) -> Result<Config, Box<dyn Error>> {
     let config_str = fs::read_to_string(config_path)?;
     let config: Config = toml::from_str(&config_str)?;
@@ -24,6 +98,29 @@ pub fn parse_config(config_path: &Path) -> Result<Config, Box<dyn Error>> {
     Ok(config)
 }
 
+// Load the config file for the running notifier, keeping defaults if there is none
+pub fn load_runtime_config(verbose: bool) {
+    if let Err(e) = reload_runtime_config() {
+        if verbose {
+            println!("Using default configuration: {}", e);
+        }
+    }
+}
+
+// Replace the running configuration, keeping the current one if the file is unusable
+pub fn reload_runtime_config() -> Result<(), Box<dyn Error>> {
+    set_runtime_config(config::create_config_manager().load_config()?);
+    Ok(())
+}
+
+pub fn set_runtime_config(config: Config) {
+    *RUNTIME_CONFIG.write().unwrap() = config;
+}
+
+pub fn runtime_config() -> Config {
+    RUNTIME_CONFIG.read().unwrap().clone()
+}
+
 // Add a function to create a default config file
 pub fn create_default_config(config_path: &Path) -> Result<(), Box<dyn Error>> {
     if config_path.exists() {
@@ -59,3 +156,82 @@ product_id = 0x0000
 
     Ok(())
 }
+
+// Set the keyboard's IDs in the config file, keeping every other setting.
+// Comments don't survive the round trip.
+#[cfg_attr(not(any(target_os = "windows", target_os = "macos")), allow(dead_code))]
+pub fn save_device_ids(
+    config_path: &Path,
+    vendor_id: u16,
+    product_id: u16,
+) -> Result<(), Box<dyn Error>> {
+    let mut table: toml::value::Table = match fs::read_to_string(config_path) {
+        Ok(config_str) => toml::from_str(&config_str)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::value::Table::new(),
+        Err(e) => return Err(e.into()),
+    };
+    for (key, id) in [("vendor_id", vendor_id), ("product_id", product_id)] {
+        table.insert(key.to_string(), toml::Value::Integer(id.into()));
+    }
+    // As a Value, which puts plain keys ahead of the sections they would end up in
+    fs::write(config_path, toml::to_string(&toml::Value::Table(table))?)?;
+    Ok(())
+}
+
+// Directory for sockets and generated files that only live while QMKonnect runs
+#[allow(dead_code)]
+pub fn runtime_dir() -> Result<PathBuf, Box<dyn Error>> {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let dir = base.join("qmkonnect");
+    fs::create_dir_all(&dir)?;
+
+    // Keep other users out of our sockets
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_device_ids() {
+        let path =
+            std::env::temp_dir().join(format!("qmkonnect-config-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+vendor_id = 0xfeed
+product_id = 0x0000
+
+[terminal]
+classes = ["kitty"]
+tmux = true
+
+[[hooks]]
+class = "slack"
+command = "slack-status busy"
+
+[mqtt]
+host = "broker.local"
+"#,
+        )
+        .unwrap();
+
+        save_device_ids(&path, 0x4653, 0x0001).unwrap();
+        let config = parse_config(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!((config.vendor_id, config.product_id), (0x4653, 0x0001));
+        assert_eq!(config.terminal.classes, ["kitty"]);
+        assert!(config.terminal.tmux);
+        assert_eq!(config.hooks[0].command, "slack-status busy");
+        assert_eq!(config.mqtt.host.as_deref(), Some("broker.local"));
+    }
+}
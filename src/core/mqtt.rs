@@ -0,0 +1,354 @@
+// Processed windows published to an MQTT broker, e.g. for Home Assistant
+// automations that follow the focused app. Built with the mqtt feature.
+#[cfg(feature = "mqtt")]
+use crate::core::notifier::{self, Notifier};
+#[cfg(feature = "mqtt")]
+use crate::core::types::WindowInfo;
+#[cfg(feature = "mqtt")]
+use rumqttc::{Client, Connection, Event, Incoming, MqttOptions, QoS};
+#[cfg(feature = "mqtt")]
+use std::error::Error;
+#[cfg(feature = "mqtt")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "mqtt")]
+use std::thread;
+#[cfg(feature = "mqtt")]
+use std::time::Duration;
+
+// How long to wait before connecting to the broker again
+#[cfg(all(feature = "mqtt", not(test)))]
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+#[cfg(all(feature = "mqtt", test))]
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+// Publishes queued up for the connection before new ones are dropped
+#[cfg(feature = "mqtt")]
+const QUEUE_SIZE: usize = 16;
+
+// [mqtt] section
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    // Broker to publish to, off unless set
+    pub host: Option<String>,
+    pub port: u16,
+    pub topic: String,
+    // 0, 1 or 2
+    pub qos: u8,
+    // Keep the last window on the broker for subscribers that come later
+    pub retain: bool,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 1883,
+            topic: "qmkonnect/window".to_string(),
+            qos: 0,
+            retain: true,
+            client_id: "qmkonnect".to_string(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+pub struct MqttSink {
+    client: Client,
+    topic: String,
+    qos: QoS,
+    retain: bool,
+    state: Arc<Mutex<SinkState>>,
+}
+
+#[cfg(feature = "mqtt")]
+#[derive(Default)]
+struct SinkState {
+    connected: bool,
+    // Published again on every reconnect, nothing older is worth sending
+    latest: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "mqtt")]
+impl Notifier for MqttSink {
+    // Raw messages and host events are encoded for the keyboard alone
+    fn notify(&self, _message: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    fn notify_window(
+        &self,
+        window_info: &WindowInfo,
+        _message: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let payload = serde_json::to_vec(window_info)?;
+        let mut state = self.state.lock().unwrap();
+        state.latest = Some(payload.clone());
+        if state.connected {
+            self.client
+                .try_publish(&self.topic, self.qos, self.retain, payload)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl MqttSink {
+    // Connects in the background and keeps reconnecting for as long as we run
+    fn connect(config: &MqttConfig, verbose: bool) -> Result<Self, String> {
+        let host = config.host.clone().ok_or("No MQTT broker configured")?;
+        let qos = rumqttc::qos(config.qos)
+            .map_err(|_| format!("Invalid MQTT QoS {}, use 0, 1 or 2", config.qos))?;
+
+        let mut options = MqttOptions::new(&config.client_id, &host, config.port);
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+        let (client, connection) = Client::new(options, QUEUE_SIZE);
+
+        let sink = Self {
+            client,
+            topic: config.topic.clone(),
+            qos,
+            retain: config.retain,
+            state: Arc::new(Mutex::new(SinkState::default())),
+        };
+        let address = format!("{}:{}", host, config.port);
+        let (client, state) = (sink.client.clone(), Arc::clone(&sink.state));
+        let (topic, retain) = (sink.topic.clone(), sink.retain);
+        thread::spawn(move || {
+            follow(connection, &address, verbose, |connected| {
+                let mut state = state.lock().unwrap();
+                state.connected = connected;
+                if let Some(payload) = state.latest.clone().filter(|_| connected) {
+                    if let Err(e) = client.try_publish(&topic, qos, retain, payload) {
+                        eprintln!("Could not publish to {}: {}", address, e);
+                    }
+                }
+            })
+        });
+        Ok(sink)
+    }
+}
+
+// Drive the connection, calling on_change whenever it comes up or goes down
+#[cfg(feature = "mqtt")]
+fn follow<F>(mut connection: Connection, address: &str, verbose: bool, on_change: F)
+where
+    F: Fn(bool),
+{
+    // Only the first of a run of failures is reported
+    let mut failing = false;
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                if verbose {
+                    println!("Connected to MQTT broker {}", address);
+                }
+                failing = false;
+                on_change(true);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if !failing {
+                    eprintln!("No connection to MQTT broker {}: {}", address, e);
+                    failing = true;
+                }
+                on_change(false);
+                thread::sleep(RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+// Publish every window the keyboard is sent, if configured
+#[cfg(feature = "mqtt")]
+pub fn start(config: &MqttConfig, verbose: bool) {
+    if config.host.is_none() {
+        return;
+    }
+    match MqttSink::connect(config, verbose) {
+        Ok(sink) => notifier::add_sink(Box::new(sink)),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+#[cfg(not(feature = "mqtt"))]
+pub fn start(config: &MqttConfig, _verbose: bool) {
+    if config.host.is_some() {
+        eprintln!("Built without MQTT support, ignoring [mqtt]");
+    }
+}
+
+#[cfg(all(test, feature = "mqtt"))]
+mod tests {
+    use super::*;
+    use std::io::{self, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{self, Receiver};
+
+    // What the broker stand-in got
+    #[derive(Debug)]
+    struct Published {
+        topic: String,
+        qos: u8,
+        retain: bool,
+        window: WindowInfo,
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+        let mut header = [0u8; 1];
+        stream.read_exact(&mut header)?;
+        let (mut length, mut shift) = (0usize, 0);
+        loop {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte)?;
+            length |= ((byte[0] & 0x7F) as usize) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body)?;
+        Ok((header[0], body))
+    }
+
+    // Just enough of a broker to accept a client and take its publishes.
+    // Hangs up on the first client after its first publish if asked to.
+    fn broker(hang_up: bool) -> (u16, Receiver<Published>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut hang_up = hang_up;
+            for mut stream in listener.incoming().flatten() {
+                while let Ok((header, body)) = read_packet(&mut stream) {
+                    match header >> 4 {
+                        // CONNECT
+                        1 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap(),
+                        // PUBLISH
+                        3 => {
+                            let qos = (header >> 1) & 0x03;
+                            let topic_length = u16::from_be_bytes([body[0], body[1]]) as usize;
+                            let topic = String::from_utf8(body[2..2 + topic_length].to_vec());
+                            let mut payload = 2 + topic_length;
+                            if qos > 0 {
+                                let id = &body[payload..payload + 2];
+                                stream.write_all(&[0x40, 0x02, id[0], id[1]]).unwrap();
+                                payload += 2;
+                            }
+                            let _ = sender.send(Published {
+                                topic: topic.unwrap(),
+                                qos,
+                                retain: header & 0x01 == 1,
+                                window: serde_json::from_slice(&body[payload..]).unwrap(),
+                            });
+                            if hang_up {
+                                hang_up = false;
+                                break;
+                            }
+                        }
+                        // PINGREQ
+                        12 => stream.write_all(&[0xD0, 0x00]).unwrap(),
+                        _ => {}
+                    }
+                }
+            }
+        });
+        (port, receiver)
+    }
+
+    fn config(port: u16) -> MqttConfig {
+        MqttConfig {
+            host: Some("127.0.0.1".to_string()),
+            port,
+            ..MqttConfig::default()
+        }
+    }
+
+    fn window(app_class: &str) -> WindowInfo {
+        let mut window_info = WindowInfo::new(app_class.to_string(), "title".to_string());
+        window_info
+            .fields
+            .insert("workspace".to_string(), "2".to_string());
+        window_info
+    }
+
+    fn recv(published: &Receiver<Published>) -> Published {
+        published.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn test_config() {
+        let config: MqttConfig = toml::from_str("host = \"broker.local\"\nqos = 1").unwrap();
+        assert_eq!(config.port, 1883);
+        assert_eq!(config.topic, "qmkonnect/window");
+        assert!(config.retain);
+        assert_eq!(config.qos, 1);
+
+        let error = MqttSink::connect(&MqttConfig { qos: 3, ..config }, false).err();
+        assert_eq!(error.unwrap(), "Invalid MQTT QoS 3, use 0, 1 or 2");
+    }
+
+    #[test]
+    fn test_publishes_windows() {
+        let (port, published) = broker(false);
+        let sink = MqttSink::connect(
+            &MqttConfig {
+                topic: "desk/focus".to_string(),
+                qos: 1,
+                ..config(port)
+            },
+            false,
+        )
+        .unwrap();
+
+        // Sent once connected
+        sink.notify_window(&window("kitty"), String::new()).unwrap();
+        let first = recv(&published);
+        assert_eq!(first.topic, "desk/focus");
+        assert_eq!((first.qos, first.retain), (1, true));
+        assert_eq!(first.window, window("kitty"));
+
+        sink.notify_window(&window("firefox"), String::new())
+            .unwrap();
+        assert_eq!(recv(&published).window, window("firefox"));
+        // Messages meant for the keyboard alone stay here
+        sink.notify("layout".to_string()).unwrap();
+        assert!(published.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn test_reconnects() {
+        let (port, published) = broker(true);
+        let sink = MqttSink::connect(
+            &MqttConfig {
+                retain: false,
+                ..config(port)
+            },
+            false,
+        )
+        .unwrap();
+
+        sink.notify_window(&window("kitty"), String::new()).unwrap();
+        let first = recv(&published);
+        assert!(!first.retain);
+        assert_eq!(first.window, window("kitty"));
+
+        // Only the latest of the windows while the broker was gone goes out
+        while sink.state.lock().unwrap().connected {
+            thread::sleep(Duration::from_millis(10));
+        }
+        sink.notify_window(&window("code"), String::new()).unwrap();
+        sink.notify_window(&window("firefox"), String::new())
+            .unwrap();
+        assert_eq!(recv(&published).window, window("firefox"));
+        assert!(published.recv_timeout(Duration::from_millis(300)).is_err());
+    }
+}
@@ -1,15 +1,36 @@
-use crate::core::types::WindowInfo;
+use crate::core::encoder::{self, SubmapMode};
+use crate::core::pipeline::{self, PipelineState};
+use crate::core::types::{HostEvent, WindowInfo};
 use once_cell::sync::Lazy;
+use std::collections::VecDeque;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
 const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(50);
 
+// Contexts remembered for pinning from the tray
+const RECENT_CONTEXTS: usize = 8;
+
+// Events a subscriber may fall behind by before it misses some
+const SUBSCRIBER_BACKLOG: usize = 64;
+
 // Trait to abstract the notification functionality
 pub trait Notifier: Send + Sync {
     fn notify(&self, message: String) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    // A window message along with the processed window it was encoded from.
+    // Sinks publish the window, the keyboard only needs the message.
+    fn notify_window(
+        &self,
+        _window_info: &WindowInfo,
+        message: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.notify(message)
+    }
 }
 
 // Real implementation that uses qmk_notifier
@@ -43,11 +64,188 @@ static NOTIFIER: Lazy<Arc<Mutex<Box<dyn Notifier>>>> =
 static DEBOUNCER: Lazy<Arc<Mutex<DebounceState>>> = Lazy::new(|| {
     Arc::new(Mutex::new(DebounceState {
         last_message: None,
+        last_window: None,
         last_activity: Instant::now(),
         timer_running: false,
     }))
 });
 
+// Notifiers besides the keyboard, which get every window the keyboard is sent
+static SINKS: Lazy<Mutex<Vec<Box<dyn Notifier>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// While paused, context is still followed but nothing reaches the keyboard
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+// Listeners for what became of each window, dropped once they hang up
+static SUBSCRIBERS: Lazy<Mutex<Vec<SyncSender<WindowEvent>>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+// Listeners for pausing and resuming, dropped once they hang up
+static PAUSE_SUBSCRIBERS: Lazy<Mutex<Vec<Sender<bool>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// Set when running as an agent, which hands windows on instead of sending them
+static FORWARD: Lazy<Mutex<Option<Sender<WindowInfo>>>> = Lazy::new(|| Mutex::new(None));
+
+// Host context beyond the debounced window message
+static CONTEXT: Lazy<Mutex<ContextState>> = Lazy::new(|| Mutex::new(ContextState::default()));
+
+#[derive(Default)]
+pub(crate) struct ContextState {
+    // Last window reported by the platform or an agent, whether or not it was sent
+    window: Option<WindowInfo>,
+    // Agent the window came from, None for the platform
+    window_source: Option<String>,
+    // Last window reported by the platform, to go back to once an agent is gone
+    local_window: Option<WindowInfo>,
+    // Last window message handed to the debouncer
+    message: Option<String>,
+    // Active Hyprland submap, empty while in the global map
+    submap: String,
+    // Last keyboard layout sent
+    layout: String,
+    pipeline: PipelineState,
+    // Context sent instead of the focused window until cleared
+    pinned: Option<Pinned>,
+    // Bumped with every pin, so an old timer can't end a newer pin
+    pins: u64,
+    // Latest first, one per class and title
+    recent: VecDeque<WindowInfo>,
+    // Windows pushed by other tools, which stand in for focus until they expire
+    pushed: Vec<Pushed>,
+    // Bumped with every push, the latest push wins a tie
+    pushes: u64,
+}
+
+// A window reported by the platform and what we did with it
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct WindowEvent {
+    pub window: WindowInfo,
+    // Tool that pushed the window or agent that sent it, None for the platform
+    pub source: Option<String>,
+    // After title parsing, enrichment and rewrite rules
+    pub processed: Option<WindowInfo>,
+    pub message: Option<String>,
+    pub outcome: Outcome,
+}
+
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Sent,
+    // Sent once focus settles, unless another window comes first
+    Debounced,
+    // Dropped by the rules, e.g. a sticky dialog or an unchanged context
+    Suppressed,
+    // Held back by a pinned context or a submap
+    Held,
+    // The pinned context itself, sent in place of focus
+    Pinned,
+    Paused,
+}
+
+#[derive(Clone)]
+pub struct Pinned {
+    pub window: WindowInfo,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub until: Option<Instant>,
+    id: u64,
+}
+
+#[derive(Clone)]
+pub struct Pushed {
+    pub source: String,
+    pub window: WindowInfo,
+    pub priority: u32,
+    pub until: Instant,
+    id: u64,
+}
+
+impl ContextState {
+    // Window context is held back while a submap stands in for it
+    fn holds_window(&self, mode: SubmapMode) -> bool {
+        mode == SubmapMode::Instead && !self.submap.is_empty()
+    }
+
+    // A window focused here, or on an agent's machine. Returns whether it
+    // went in or out of fullscreen.
+    pub(crate) fn focus(&mut self, focused: &WindowInfo, source: Option<&str>) -> Option<bool> {
+        let was_fullscreen = self.window.as_ref().is_some_and(|window| window.fullscreen);
+        self.window = Some(focused.clone());
+        self.window_source = source.map(str::to_string);
+        if source.is_none() {
+            self.local_window = Some(focused.clone());
+        }
+        (was_fullscreen != focused.fullscreen).then_some(focused.fullscreen)
+    }
+
+    // Local focus counts again once the agent it went to is gone. Returns
+    // false for any other agent.
+    pub(crate) fn agent_gone(&mut self, source: &str) -> bool {
+        if self.window_source.as_deref() != Some(source) {
+            return false;
+        }
+        self.window = self.local_window.clone();
+        self.window_source = None;
+        true
+    }
+
+    // The winning pushed window, or else the focused one, with where it came
+    // from and whether an agent enriched it already
+    pub(crate) fn current(&mut self) -> Option<(Option<String>, WindowInfo, bool)> {
+        if let Some(pushed) = self.top_push() {
+            return Some((Some(pushed.source.clone()), pushed.window.clone(), false));
+        }
+        let window = self.window.clone()?;
+        let remote = self.window_source.is_some();
+        Some((self.window_source.clone(), window, remote))
+    }
+
+    // Agents enrich their windows before passing them on, so theirs only go
+    // through the rules
+    pub(crate) fn process(
+        &mut self,
+        window_info: &WindowInfo,
+        remote: bool,
+        config: &crate::core::Config,
+    ) -> Option<WindowInfo> {
+        if remote {
+            pipeline::apply_rules(window_info, config, &mut self.pipeline)
+        } else {
+            pipeline::process(window_info, config, &mut self.pipeline)
+        }
+    }
+
+    // The pushed window that wins, dropping any that ran out
+    fn top_push(&mut self) -> Option<&Pushed> {
+        let now = Instant::now();
+        self.pushed.retain(|pushed| pushed.until > now);
+        self.pushed
+            .iter()
+            .max_by_key(|pushed| (pushed.priority, pushed.id))
+    }
+
+    fn remember(&mut self, window_info: &WindowInfo) {
+        self.recent.retain(|recent| {
+            recent.app_class != window_info.app_class || recent.title != window_info.title
+        });
+        self.recent.push_front(window_info.clone());
+        self.recent.truncate(RECENT_CONTEXTS);
+    }
+
+    // Returns the window to send again when leaving a submap
+    fn set_submap(&mut self, name: &str) -> Option<WindowInfo> {
+        let leaving = !self.submap.is_empty() && name.is_empty();
+        self.submap = name.to_string();
+        if leaving {
+            // The submap message replaced whatever context the keyboard had
+            self.pipeline = PipelineState::default();
+            self.window.clone()
+        } else {
+            None
+        }
+    }
+}
+
 // For testing: Set a custom notifier
 #[cfg(test)]
 pub fn set_notifier(notifier: Box<dyn Notifier>) {
@@ -62,6 +260,8 @@ pub fn set_notifier(notifier: Box<dyn Notifier>) {
 
 struct DebounceState {
     last_message: Option<String>,
+    // Processed window the last message was encoded from
+    last_window: Option<WindowInfo>,
     last_activity: Instant,
     timer_running: bool,
 }
@@ -74,11 +274,414 @@ fn get_notifier() -> Arc<Mutex<Box<dyn Notifier>>> {
     Arc::clone(&NOTIFIER)
 }
 
+// Also hand windows to the sink from now on. Sinks are called while the
+// window is being sent, so anything slow belongs on a thread of their own.
+#[cfg_attr(not(any(feature = "mqtt", feature = "webhook")), allow(dead_code))]
+pub fn add_sink(sink: Box<dyn Notifier>) {
+    SINKS.lock().unwrap().push(sink);
+}
+
+// Send a window message to the keyboard and the window to every sink. Sinks
+// get it even when the keyboard is missing.
+fn send_window(
+    notifier: &dyn Notifier,
+    window_info: &WindowInfo,
+    message: String,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for sink in SINKS.lock().unwrap().iter() {
+        if let Err(e) = sink.notify_window(window_info, message.clone()) {
+            eprintln!("Error notifying sink: {}", e);
+        }
+    }
+    notifier.notify_window(window_info, message)
+}
+
+// Last window reported by the platform, to send again once what it is enriched with changes
+pub fn last_window() -> Option<WindowInfo> {
+    CONTEXT.lock().unwrap().window.clone()
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn last_message() -> Option<String> {
+    CONTEXT.lock().unwrap().message.clone()
+}
+
+// Send the last window again, e.g. after the configuration changed
+pub fn refresh(verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if is_forwarding() {
+        // Enriched again on the way out
+        return match last_window() {
+            Some(window_info) => notify_qmk(&window_info, verbose),
+            None => Ok(()),
+        };
+    }
+    notify(None, verbose)
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+// The keyboard catches up with the current window on resume
+pub fn set_paused(paused: bool, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let was_paused = PAUSED.swap(paused, Ordering::SeqCst);
+    if was_paused != paused {
+        if verbose {
+            println!("{}", if paused { "Paused" } else { "Resumed" });
+        }
+        PAUSE_SUBSCRIBERS
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(paused).is_ok());
+    }
+    if was_paused && !paused {
+        // Rules that only send on a change think the keyboard is up to date
+        CONTEXT.lock().unwrap().pipeline = PipelineState::default();
+        refresh(verbose)?;
+    }
+    Ok(())
+}
+
+// Send a fixed context until cleared, or until the duration is up. Focus is
+// still followed meanwhile and sent again once the pin ends.
+pub fn pin_context(
+    window_info: WindowInfo,
+    duration: Option<Duration>,
+    verbose: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let message = crate::core::runtime_config()
+        .encoder
+        .encode_window(&window_info);
+    let id = {
+        let mut context = CONTEXT.lock().unwrap();
+        context.pins += 1;
+        context.pinned = Some(Pinned {
+            window: window_info.clone(),
+            until: duration.map(|duration| Instant::now() + duration),
+            id: context.pins,
+        });
+        context.message = Some(message.clone());
+        context.pins
+    };
+
+    if let Some(duration) = duration {
+        thread::spawn(move || {
+            thread::sleep(duration);
+            if let Err(e) = unpin(Some(id), verbose) {
+                eprintln!("Error notifying QMK: {}", e);
+            }
+        });
+    }
+
+    if verbose {
+        println!("Pinned context: {}", encoder::sanitize(&message));
+    }
+    let notifier = get_notifier();
+    let notifier = notifier.lock().unwrap();
+    send_pinned(notifier.as_ref(), window_info, message)
+}
+
+// Send a pinned context like any other window, in place of whatever the
+// debouncer was still going to send
+fn send_pinned(
+    notifier: &dyn Notifier,
+    window_info: WindowInfo,
+    message: String,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    {
+        let debouncer = get_debouncer();
+        let mut state = debouncer.lock().unwrap();
+        state.last_message = None;
+        state.last_window = None;
+    }
+
+    let paused = is_paused();
+    publish(WindowEvent {
+        window: window_info.clone(),
+        source: None,
+        processed: Some(window_info.clone()),
+        message: Some(message.clone()),
+        outcome: if paused {
+            Outcome::Paused
+        } else {
+            Outcome::Pinned
+        },
+    });
+    if paused {
+        return Ok(());
+    }
+    send_window(notifier, &window_info, message)
+}
+
+pub fn clear_pin(verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    unpin(None, verbose)
+}
+
+// Only ends the given pin if one is passed
+fn unpin(id: Option<u64>, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    {
+        let mut context = CONTEXT.lock().unwrap();
+        match &context.pinned {
+            Some(pinned) if id.is_none_or(|id| id == pinned.id) => {}
+            _ => return Ok(()),
+        }
+        context.pinned = None;
+        context.pipeline = PipelineState::default();
+    }
+
+    if verbose {
+        println!("Following focus again");
+    }
+    refresh(verbose)
+}
+
+pub fn pinned() -> Option<Pinned> {
+    CONTEXT.lock().unwrap().pinned.clone()
+}
+
+// Contexts recently sent to the keyboard, latest first
+#[cfg_attr(all(target_os = "linux", feature = "hyprland"), allow(dead_code))]
+pub fn recent_contexts() -> Vec<WindowInfo> {
+    CONTEXT.lock().unwrap().recent.iter().cloned().collect()
+}
+
+// Every window notify_qmk sees from now on
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn subscribe() -> Receiver<WindowEvent> {
+    let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
+
+// Whether we are paused, every time that changes from now on
+#[cfg_attr(not(all(target_os = "linux", feature = "dbus")), allow(dead_code))]
+pub fn subscribe_paused() -> Receiver<bool> {
+    let (sender, receiver) = mpsc::channel();
+    PAUSE_SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
+
+// A subscriber that falls behind misses events rather than holding us up
+fn publish(event: WindowEvent) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|sender| match sender.try_send(event.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+}
+
+// Stand in for the focused window until the duration is up. Each source has
+// one window at a time, the highest priority wins among sources.
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn push_window(
+    source: String,
+    window_info: WindowInfo,
+    priority: u32,
+    duration: Duration,
+    verbose: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if verbose {
+        println!(
+            "Window pushed by '{}' for {:?}: {} {}",
+            source, duration, window_info.app_class, window_info.title
+        );
+    }
+    let id = {
+        let mut context = CONTEXT.lock().unwrap();
+        context.pushes += 1;
+        let id = context.pushes;
+        context.pushed.retain(|pushed| pushed.source != source);
+        context.pushed.push(Pushed {
+            source: source.clone(),
+            window: window_info,
+            priority,
+            until: Instant::now() + duration,
+            id,
+        });
+        id
+    };
+
+    thread::spawn(move || {
+        thread::sleep(duration);
+        if let Err(e) = unpush(&source, Some(id), verbose) {
+            eprintln!("Error notifying QMK: {}", e);
+        }
+    });
+    refresh(verbose)
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn clear_push(source: &str, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    unpush(source, None, verbose)
+}
+
+// Only ends the given push if one is passed, a newer one from the same source stays
+fn unpush(
+    source: &str,
+    id: Option<u64>,
+    verbose: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    {
+        let mut context = CONTEXT.lock().unwrap();
+        let count = context.pushed.len();
+        context
+            .pushed
+            .retain(|pushed| pushed.source != source || id.is_some_and(|id| id != pushed.id));
+        if context.pushed.len() == count {
+            return Ok(());
+        }
+    }
+
+    if verbose {
+        println!("Window pushed by '{}' ended", source);
+    }
+    refresh(verbose)
+}
+
+// Pushed windows that haven't run out, whether or not they win
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn pushed() -> Vec<Pushed> {
+    let now = Instant::now();
+    let context = CONTEXT.lock().unwrap();
+    context
+        .pushed
+        .iter()
+        .filter(|pushed| pushed.until > now)
+        .cloned()
+        .collect()
+}
+
+// Hand every window on to an agent's connection from now on
+pub fn forward_to(sender: Sender<WindowInfo>) {
+    *FORWARD.lock().unwrap() = Some(sender);
+}
+
+fn is_forwarding() -> bool {
+    FORWARD.lock().unwrap().is_some()
+}
+
+// A window focused on another machine, which counts as focus until something
+// is focused here or on another machine. None once the agent is gone.
+pub fn notify_remote(
+    source: &str,
+    window_info: Option<&WindowInfo>,
+    verbose: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(window_info) = window_info {
+        return notify(Some((window_info, Some(source))), verbose);
+    }
+
+    if !CONTEXT.lock().unwrap().agent_gone(source) {
+        return Ok(());
+    }
+    if verbose {
+        println!("Agent {} is gone, following local focus again", source);
+    }
+    refresh(verbose)
+}
+
+// Returns whether we are paused now
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn toggle_paused(verbose: bool) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let paused = !is_paused();
+    set_paused(paused, verbose)?;
+    Ok(paused)
+}
+
+// Send a message as is, paused or not
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn send_raw(message: String, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if verbose {
+        println!("Notified QMK (raw): {}", encoder::sanitize(&message));
+    }
+    get_notifier().lock().unwrap().notify(message)
+}
+
 pub fn notify_qmk(
     window_info: &WindowInfo,
     verbose: bool,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let message = format!("{}{}{}", window_info.app_class, "\x1D", window_info.title);
+    if let Some(forward) = FORWARD.lock().unwrap().as_ref() {
+        CONTEXT.lock().unwrap().window = Some(window_info.clone());
+        // Processes, tabs and the like are only known here
+        let config = crate::core::runtime_config();
+        let _ = forward.send(pipeline::enrich(window_info, &config));
+        return Ok(());
+    }
+    notify(Some((window_info, None)), verbose)
+}
+
+// Process the winning pushed window, or else the focused one and the agent it
+// came from, if any. Without a newly focused window, the last one is sent again.
+fn notify(
+    focused: Option<(&WindowInfo, Option<&str>)>,
+    verbose: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let config = crate::core::runtime_config();
+    let (fullscreen_changed, source, window_info, held, processed) = {
+        let mut context = CONTEXT.lock().unwrap();
+        let fullscreen_changed =
+            focused.and_then(|(focused, source)| context.focus(focused, source));
+        let Some((source, window_info, remote)) = context.current() else {
+            return Ok(());
+        };
+
+        let held = context.pinned.is_some() || context.holds_window(config.encoder.submap);
+        let processed = if context.pinned.is_some() {
+            if verbose {
+                println!("Holding window context while a context is pinned");
+            }
+            None
+        } else if context.holds_window(config.encoder.submap) {
+            if verbose {
+                println!(
+                    "Holding window context while submap '{}' is active",
+                    context.submap
+                );
+            }
+            None
+        } else {
+            context.process(&window_info, remote, &config)
+        };
+        (fullscreen_changed, source, window_info, held, processed)
+    };
+
+    if let Some(fullscreen) = fullscreen_changed {
+        notify_event(&HostEvent::Fullscreen(fullscreen), verbose)?;
+    }
+
+    let event = |processed: Option<WindowInfo>, message: Option<String>, outcome| WindowEvent {
+        window: window_info.clone(),
+        source: source.clone(),
+        processed,
+        message,
+        outcome,
+    };
+    let Some(processed) = processed else {
+        let outcome = if held {
+            Outcome::Held
+        } else {
+            Outcome::Suppressed
+        };
+        publish(event(None, None, outcome));
+        return Ok(());
+    };
+    let message = config.encoder.encode_window(&processed);
+    {
+        let mut context = CONTEXT.lock().unwrap();
+        context.message = Some(message.clone());
+        context.remember(&processed);
+    }
+
+    if PAUSED.load(Ordering::SeqCst) {
+        if verbose {
+            println!("Paused, not sending: {}", encoder::sanitize(&message));
+        }
+        publish(event(Some(processed), Some(message), Outcome::Paused));
+        return Ok(());
+    }
     let debouncer = get_debouncer();
 
     // Wrap in a block to limit the lock scope
@@ -101,6 +704,7 @@ pub fn notify_qmk(
 
         // Store the latest message
         state.last_message = Some(message.clone());
+        state.last_window = Some(processed.clone());
 
         // Determine if we should send immediately
         // For tests, use a slightly higher threshold to avoid timing issues
@@ -122,10 +726,21 @@ pub fn notify_qmk(
         should_send_now
     };
 
+    let outcome = if should_send_now {
+        Outcome::Sent
+    } else {
+        Outcome::Debounced
+    };
+    publish(event(
+        Some(processed.clone()),
+        Some(message.clone()),
+        outcome,
+    ));
+
     // Send immediately if needed
     if should_send_now {
         if verbose {
-            let sanitized_message = message.replace('\x1D', "|");
+            let sanitized_message = encoder::sanitize(&message);
             println!("Notified QMK (immediate): {}", sanitized_message);
         }
         let notifier = get_notifier();
@@ -134,15 +749,64 @@ pub fn notify_qmk(
         #[cfg(test)]
         println!("Sending notification immediately: {}", message);
 
-        notifier.notify(message)?;
+        send_window(notifier.as_ref(), &processed, message)?;
     } else if verbose {
-        let sanitized_message = message.replace('\x1D', "|");
+        let sanitized_message = encoder::sanitize(&message);
         println!("Debouncing notification: {}", sanitized_message);
     }
 
     Ok(())
 }
 
+// Send a host event right away. Events are rare and each one matters, so they
+// skip the debouncer.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn notify_event(event: &HostEvent, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let encoder_config = crate::core::runtime_config().encoder;
+    // An agent has no keyboard of its own
+    if !encoder_config.forwards(event) || is_forwarding() {
+        return Ok(());
+    }
+
+    let resume_window = {
+        let mut context = CONTEXT.lock().unwrap();
+        match event {
+            HostEvent::Submap(name) => context.set_submap(name),
+            HostEvent::Layout(name) => {
+                // Every attached keyboard reports the same switch
+                if context.layout == *name {
+                    return Ok(());
+                }
+                context.layout = name.clone();
+                None
+            }
+            // Every request for attention is worth a blink
+            HostEvent::Attention(_) | HostEvent::Fullscreen(_) | HostEvent::Mode(_) => None,
+        }
+    };
+
+    let message = encoder_config.encode_event(event);
+    if PAUSED.load(Ordering::SeqCst) {
+        if verbose {
+            println!("Paused, not sending: {}", encoder::sanitize(&message));
+        }
+        return Ok(());
+    }
+    if verbose {
+        println!("Notified QMK (event): {}", encoder::sanitize(&message));
+    }
+    get_notifier().lock().unwrap().notify(message)?;
+
+    // Hand the keyboard back to window context once the submap is left
+    if encoder_config.submap == SubmapMode::Instead {
+        if let Some(window_info) = resume_window {
+            notify_qmk(&window_info, verbose)?;
+        }
+    }
+
+    Ok(())
+}
+
 fn debounce_timer(debouncer: Arc<Mutex<DebounceState>>, notifier: Arc<Mutex<Box<dyn Notifier>>>) {
     loop {
         thread::sleep(Duration::from_millis(10)); // Check every 100ms
@@ -154,8 +818,7 @@ fn debounce_timer(debouncer: Arc<Mutex<DebounceState>>, notifier: Arc<Mutex<Box<
 
             // If 100ms have passed without activity, send the last message
             if elapsed >= Duration::from_millis(100) {
-                let message = state.last_message.clone();
-                state.last_message = None;
+                let message = state.last_message.take().zip(state.last_window.take());
                 state.timer_running = false;
                 (true, message, false) // Exit after sending
             } else {
@@ -163,10 +826,12 @@ fn debounce_timer(debouncer: Arc<Mutex<DebounceState>>, notifier: Arc<Mutex<Box<
             }
         };
 
-        // Send the debounced message if needed
-        if let Some(message) = message_to_send {
+        // Send the debounced message if needed, unless paused in the meantime
+        if let Some((message, window_info)) =
+            message_to_send.filter(|_| !PAUSED.load(Ordering::SeqCst))
+        {
             if verbose_mode {
-                let sanitized_message = message.replace('\x1D', "|");
+                let sanitized_message = encoder::sanitize(&message);
                 println!("Notified QMK (debounced): {}", sanitized_message);
             }
 
@@ -176,7 +841,7 @@ fn debounce_timer(debouncer: Arc<Mutex<DebounceState>>, notifier: Arc<Mutex<Box<
             let notifier_guard = notifier.lock().unwrap();
 
             // Use explicit error handling for better debugging
-            if let Err(e) = notifier_guard.notify(message) {
+            if let Err(e) = send_window(notifier_guard.as_ref(), &window_info, message) {
                 eprintln!("Error sending debounced notification: {}", e);
             }
         }
@@ -244,6 +909,7 @@ mod tests {
         let mut state = DEBOUNCER.lock().unwrap();
         *state = DebounceState {
             last_message: None,
+            last_window: None,
             last_activity: Instant::now(),
             timer_running: false,
         };
@@ -399,6 +1065,152 @@ mod tests {
         assert_eq!(MockNotifier::get_call_count(), 1);
     }
 
+    #[test]
+    fn test_submap_holds_window_context() {
+        let mut context = ContextState::default();
+        let window_info = WindowInfo::new("kitty".to_string(), "nvim".to_string());
+        context.window = Some(window_info.clone());
+
+        assert_eq!(context.set_submap("resize"), None);
+        assert!(context.holds_window(SubmapMode::Instead));
+        assert!(!context.holds_window(SubmapMode::Alongside));
+
+        // Switching between submaps doesn't release the window
+        assert_eq!(context.set_submap("move"), None);
+
+        assert_eq!(context.set_submap(""), Some(window_info));
+        assert!(!context.holds_window(SubmapMode::Instead));
+
+        // Already in the global map, nothing to resend
+        assert_eq!(context.set_submap(""), None);
+    }
+
+    #[test]
+    fn test_top_push() {
+        let mut context = ContextState::default();
+        assert!(context.top_push().is_none());
+
+        let mut push = |source: &str, priority, duration| {
+            context.pushes += 1;
+            context.pushed.push(Pushed {
+                source: source.to_string(),
+                window: WindowInfo::new(source.to_string(), String::new()),
+                priority,
+                until: Instant::now() + duration,
+                id: context.pushes,
+            });
+        };
+        push("vm", 1, Duration::from_secs(60));
+        push("rdp", 0, Duration::from_secs(60));
+        push("expired", 5, Duration::ZERO);
+
+        // Priority first, then the latest push
+        assert_eq!(context.top_push().unwrap().source, "vm");
+        assert_eq!(context.pushed.len(), 2);
+        context.pushed.retain(|pushed| pushed.source != "vm");
+        assert_eq!(context.top_push().unwrap().source, "rdp");
+    }
+
+    #[test]
+    fn test_sinks_get_windows() {
+        struct Recorder(mpsc::Sender<(WindowInfo, String)>);
+        impl Notifier for Recorder {
+            fn notify(&self, _message: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+                Ok(())
+            }
+
+            fn notify_window(
+                &self,
+                window_info: &WindowInfo,
+                message: String,
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                let _ = self.0.send((window_info.clone(), message));
+                Ok(())
+            }
+        }
+        struct Unplugged;
+        impl Notifier for Unplugged {
+            fn notify(&self, _message: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+                Err("no keyboard".into())
+            }
+        }
+
+        let (sender, windows) = mpsc::channel();
+        add_sink(Box::new(Recorder(sender)));
+        let window_info = WindowInfo::new("SinkApp".to_string(), "Title".to_string());
+
+        // A missing keyboard doesn't keep the window from the sinks
+        assert!(send_window(&Unplugged, &window_info, "message".to_string()).is_err());
+        let (received, message) = windows
+            .iter()
+            .find(|(window, _)| window.app_class == "SinkApp")
+            .unwrap();
+        assert_eq!(received, window_info);
+        assert_eq!(message, "message");
+    }
+
+    #[test]
+    fn test_pin_reaches_sinks_and_subscribers() {
+        struct Recorder(mpsc::Sender<String>);
+        impl Notifier for Recorder {
+            fn notify(&self, message: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+                let _ = self.0.send(message);
+                Ok(())
+            }
+
+            fn notify_window(
+                &self,
+                window_info: &WindowInfo,
+                _message: String,
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                let _ = self.0.send(window_info.app_class.clone());
+                Ok(())
+            }
+        }
+
+        let (sink, sunk) = mpsc::channel();
+        add_sink(Box::new(Recorder(sink)));
+        let events = subscribe();
+        let window_info = WindowInfo::new("PinnedApp".to_string(), "Focus".to_string());
+        get_debouncer().lock().unwrap().last_message = Some("stale".to_string());
+
+        let (keyboard, sent) = mpsc::channel();
+        send_pinned(
+            &Recorder(keyboard),
+            window_info.clone(),
+            "pinned".to_string(),
+        )
+        .unwrap();
+        assert_eq!(sent.try_iter().collect::<Vec<_>>(), ["PinnedApp"]);
+        assert!(sunk.try_iter().any(|app_class| app_class == "PinnedApp"));
+        // Nothing left for the debouncer to send after the pin
+        assert!(get_debouncer().lock().unwrap().last_message.is_none());
+
+        let event = events
+            .try_iter()
+            .find(|event| event.window.app_class == "PinnedApp")
+            .unwrap();
+        assert_eq!(event.outcome, Outcome::Pinned);
+        assert_eq!(event.processed, Some(window_info));
+        assert_eq!(event.message.as_deref(), Some("pinned"));
+    }
+
+    #[test]
+    fn test_recent_contexts() {
+        let mut context = ContextState::default();
+        for i in 0..RECENT_CONTEXTS + 2 {
+            context.remember(&WindowInfo::new("kitty".to_string(), i.to_string()));
+        }
+        context.remember(&WindowInfo::new("kitty".to_string(), "5".to_string()));
+
+        let titles: Vec<&str> = context
+            .recent
+            .iter()
+            .map(|window| window.title.as_str())
+            .collect();
+        assert_eq!(titles, ["5", "9", "8", "7", "6", "4", "3", "2"]);
+    }
+
     #[test]
     fn test_threads_dont_interfere() {
         reset_test_state();
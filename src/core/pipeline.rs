@@ -0,0 +1,415 @@
+// Rules applied to every focused window before it is sent to the keyboard
+use crate::core::types::WindowInfo;
+use crate::core::Config;
+use crate::core::{browser, terminal, title};
+
+// [gaming] section
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub struct GamingConfig {
+    // Class patterns of games, e.g. "steam_app*". Gaming mode is off while empty.
+    pub classes: Vec<String>,
+    // Context sent while one of them is focused and fullscreen
+    pub app_class: String,
+    pub title: String,
+}
+
+impl Default for GamingConfig {
+    fn default() -> Self {
+        Self {
+            classes: Vec::new(),
+            app_class: "_GAMING".to_string(),
+            title: String::new(),
+        }
+    }
+}
+
+// [sticky] section
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub struct StickyConfig {
+    // Keep the parent's context for dialogs and transient windows
+    pub dialogs: bool,
+    // Class patterns that never take over the context, e.g. "xdg-desktop-portal*"
+    pub classes: Vec<String>,
+}
+
+impl Default for StickyConfig {
+    fn default() -> Self {
+        Self {
+            dialogs: true,
+            classes: Vec::new(),
+        }
+    }
+}
+
+// [[workspaces]] entries
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct WorkspaceRule {
+    // Workspace id or name pattern, e.g. "9" or "special:scratchpad"
+    pub workspace: String,
+    // Context sent for anything focused on that workspace
+    pub app_class: String,
+    #[serde(default)]
+    pub title: String,
+}
+
+impl WorkspaceRule {
+    fn matches(&self, window_info: &WindowInfo) -> bool {
+        ["workspace", "workspace_id"].iter().any(|field| {
+            window_info
+                .fields
+                .get(*field)
+                .is_some_and(|value| matches_pattern(&self.workspace, value))
+        })
+    }
+}
+
+// What the rules remember between windows
+#[derive(Default)]
+pub struct PipelineState {
+    // Last window that took over the context, the parent of any dialog
+    parent: Option<WindowInfo>,
+    gaming: bool,
+    workspace_rule: Option<usize>,
+}
+
+// Turn the focused window into the context the keyboard should see. Returns
+// None when the keyboard already has that context.
+pub fn process(
+    window_info: &WindowInfo,
+    config: &Config,
+    state: &mut PipelineState,
+) -> Option<WindowInfo> {
+    apply_rules(&enrich(window_info, config), config, state)
+}
+
+// Add what only this machine can tell about the window, e.g. the process
+// running in a terminal or the browser's active tab
+pub fn enrich(window_info: &WindowInfo, config: &Config) -> WindowInfo {
+    // Before the terminal stage may replace the title
+    let window_info = &title::parse(window_info, &config.titles);
+    let window_info = &terminal::enrich(window_info, &config.terminal);
+    browser::enrich(window_info, &config.browser)
+}
+
+// The rest of process, for windows enriched where they were focused
+pub fn apply_rules(
+    window_info: &WindowInfo,
+    config: &Config,
+    state: &mut PipelineState,
+) -> Option<WindowInfo> {
+    // The keyboard still has the parent's context
+    if is_sticky(window_info, &config.sticky, state) {
+        return None;
+    }
+    if let Some(context) = workspace_override(window_info, &config.workspaces, state) {
+        return context;
+    }
+    gaming(window_info, &config.gaming, state)
+}
+
+// Dialogs, popups and configured classes stay in the context of the window
+// they were opened from
+fn is_sticky(window_info: &WindowInfo, config: &StickyConfig, state: &mut PipelineState) -> bool {
+    let Some(parent) = &state.parent else {
+        state.parent = Some(window_info.clone());
+        return false;
+    };
+
+    // Hyprland doesn't know about transients, but dialogs float and belong
+    // to the same process
+    let is_dialog = window_info.transient
+        || (window_info.floating && window_info.pid.is_some() && window_info.pid == parent.pid);
+    let sticky = (config.dialogs && is_dialog)
+        || config
+            .classes
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &window_info.app_class));
+
+    if !sticky {
+        state.parent = Some(window_info.clone());
+    }
+    sticky
+}
+
+// The first rule for the focused workspace wins over whatever app is focused
+fn workspace_override(
+    window_info: &WindowInfo,
+    rules: &[WorkspaceRule],
+    state: &mut PipelineState,
+) -> Option<Option<WindowInfo>> {
+    let index = rules.iter().position(|rule| rule.matches(window_info));
+    let previous = std::mem::replace(&mut state.workspace_rule, index);
+    let index = index?;
+
+    // Other stages start over once the workspace is left
+    state.gaming = false;
+    if previous == Some(index) {
+        return Some(None);
+    }
+
+    let rule = &rules[index];
+    Some(Some(fixed_context(
+        window_info,
+        &rule.app_class,
+        &rule.title,
+    )))
+}
+
+fn gaming(
+    window_info: &WindowInfo,
+    config: &GamingConfig,
+    state: &mut PipelineState,
+) -> Option<WindowInfo> {
+    let is_game = window_info.fullscreen
+        && config
+            .classes
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &window_info.app_class));
+    let was_game = std::mem::replace(&mut state.gaming, is_game);
+
+    if !is_game {
+        return Some(window_info.clone());
+    }
+    // Games retitle themselves constantly, which must not flap the layer
+    if was_game {
+        return None;
+    }
+
+    Some(fixed_context(window_info, &config.app_class, &config.title))
+}
+
+// Replace what the keyboard sees of a window, keeping its extra fields
+fn fixed_context(window_info: &WindowInfo, app_class: &str, title: &str) -> WindowInfo {
+    WindowInfo {
+        app_class: app_class.to_string(),
+        title: title.to_string(),
+        ..window_info.clone()
+    }
+}
+
+// Case-insensitive match where `*` stands for any run of characters, as in
+// the firmware's DEFINE_SERIAL_LAYERS patterns
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    let mut parts = pattern.split('*');
+
+    // Without any `*` the pattern is a single part and must match exactly
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fullscreen(app_class: &str, title: &str) -> WindowInfo {
+        let mut window_info = WindowInfo::new(app_class.to_string(), title.to_string());
+        window_info.fullscreen = true;
+        window_info
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("steam_app*", "steam_app_570"));
+        assert!(matches_pattern("*chrome*", "Google-chrome"));
+        assert!(matches_pattern("*calculator", "gnome-calculator"));
+        assert!(matches_pattern("cs2", "CS2"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("a*b*c", "abc"));
+
+        assert!(!matches_pattern("cs2", "cs2-launcher"));
+        assert!(!matches_pattern("steam_app*", "steam"));
+        assert!(!matches_pattern("*calculator", "calculator.desktop"));
+        // The prefix and suffix may not overlap
+        assert!(!matches_pattern("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn test_gaming_mode() {
+        let config: Config = toml::from_str(
+            r#"
+            vendor_id = 0xfeed
+            product_id = 0x0000
+
+            [gaming]
+            classes = ["steam_app*"]
+            "#,
+        )
+        .unwrap();
+        let mut state = PipelineState::default();
+
+        // Windowed games keep their own context
+        let windowed = WindowInfo::new("steam_app_570".to_string(), "Dota 2".to_string());
+        assert_eq!(
+            process(&windowed, &config, &mut state),
+            Some(windowed.clone())
+        );
+
+        assert_eq!(
+            process(&fullscreen("steam_app_570", "Dota 2"), &config, &mut state),
+            Some(fullscreen("_GAMING", ""))
+        );
+        assert_eq!(
+            process(
+                &fullscreen("steam_app_570", "Dota 2 - 60 fps"),
+                &config,
+                &mut state
+            ),
+            None
+        );
+
+        let browser = fullscreen("firefox", "YouTube");
+        assert_eq!(
+            process(&browser, &config, &mut state),
+            Some(browser.clone())
+        );
+    }
+
+    fn on_workspace(app_class: &str, id: &str, name: &str) -> WindowInfo {
+        let mut window_info = WindowInfo::new(app_class.to_string(), String::new());
+        window_info
+            .fields
+            .insert("workspace_id".to_string(), id.to_string());
+        window_info
+            .fields
+            .insert("workspace".to_string(), name.to_string());
+        window_info
+    }
+
+    #[test]
+    fn test_workspace_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+            vendor_id = 0xfeed
+            product_id = 0x0000
+
+            [[workspaces]]
+            workspace = "9"
+            app_class = "_GAMING"
+
+            [[workspaces]]
+            workspace = "special:scratch*"
+            app_class = "_TERMINAL"
+            title = "scratchpad"
+            "#,
+        )
+        .unwrap();
+        let mut state = PipelineState::default();
+
+        let context = process(&on_workspace("steam", "9", "9"), &config, &mut state).unwrap();
+        assert_eq!(context.app_class, "_GAMING");
+        assert_eq!(context.fields["workspace"], "9");
+
+        // Anything else focused on the same workspace changes nothing
+        assert_eq!(
+            process(&on_workspace("discord", "9", "9"), &config, &mut state),
+            None
+        );
+
+        let context = process(
+            &on_workspace("kitty", "-98", "special:scratchpad"),
+            &config,
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(context.app_class, "_TERMINAL");
+        assert_eq!(context.title, "scratchpad");
+
+        let browser = on_workspace("firefox", "2", "web");
+        assert_eq!(
+            process(&browser, &config, &mut state),
+            Some(browser.clone())
+        );
+
+        // Coming back applies the rule again
+        let context = process(&on_workspace("steam", "9", "9"), &config, &mut state).unwrap();
+        assert_eq!(context.app_class, "_GAMING");
+    }
+
+    #[test]
+    fn test_sticky_dialogs() {
+        let config = Config::default();
+        let mut state = PipelineState::default();
+
+        let mut browser = WindowInfo::new("firefox".to_string(), "GitHub".to_string());
+        browser.pid = Some(4242);
+        assert_eq!(
+            process(&browser, &config, &mut state),
+            Some(browser.clone())
+        );
+
+        // X11 transients and dialogs
+        let mut save_as = WindowInfo::new("firefox".to_string(), "Save As".to_string());
+        save_as.transient = true;
+        assert_eq!(process(&save_as, &config, &mut state), None);
+
+        // Hyprland floating windows of the same process
+        let mut popup = WindowInfo::new("firefox".to_string(), "Library".to_string());
+        popup.floating = true;
+        popup.pid = Some(4242);
+        assert_eq!(process(&popup, &config, &mut state), None);
+
+        // A floating window of another process is an app of its own
+        let mut calculator = WindowInfo::new("gnome-calculator".to_string(), String::new());
+        calculator.floating = true;
+        calculator.pid = Some(5000);
+        assert_eq!(
+            process(&calculator, &config, &mut state),
+            Some(calculator.clone())
+        );
+    }
+
+    #[test]
+    fn test_sticky_classes() {
+        let config: Config = toml::from_str(
+            r#"
+            vendor_id = 0xfeed
+            product_id = 0x0000
+
+            [sticky]
+            dialogs = false
+            classes = ["xdg-desktop-portal*"]
+            "#,
+        )
+        .unwrap();
+        let mut state = PipelineState::default();
+
+        let editor = WindowInfo::new("code".to_string(), "main.rs".to_string());
+        assert_eq!(process(&editor, &config, &mut state), Some(editor.clone()));
+
+        let picker = WindowInfo::new("xdg-desktop-portal-gtk".to_string(), "Open".to_string());
+        assert_eq!(process(&picker, &config, &mut state), None);
+
+        let mut dialog = WindowInfo::new("code".to_string(), "Save".to_string());
+        dialog.transient = true;
+        assert_eq!(process(&dialog, &config, &mut state), Some(dialog.clone()));
+    }
+
+    #[test]
+    fn test_gaming_mode_off_by_default() {
+        let mut state = PipelineState::default();
+        let game = fullscreen("steam_app_570", "Dota 2");
+        assert_eq!(
+            process(&game, &Config::default(), &mut state),
+            Some(game.clone())
+        );
+    }
+}
@@ -0,0 +1,449 @@
+// Window context from other machines, e.g. a VM or a second computer behind a
+// KVM while the keyboard stays plugged in here. `qmkonnect agent` connects,
+// sends a hello line with the token, then one enriched window per line as JSON.
+use crate::core::notifier;
+use crate::core::types::WindowInfo;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// How long the agent waits before connecting again
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+// How long either end waits for the other's side of the hello
+#[cfg(not(test))]
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+#[cfg(test)]
+const HELLO_TIMEOUT: Duration = Duration::from_millis(200);
+
+// A window is a lot less than this, anything longer is not an agent
+const MAX_LINE: u64 = 64 * 1024;
+
+// [remote] section
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(default)]
+pub struct RemoteConfig {
+    // Address to accept agents on, e.g. "0.0.0.0:7878". Off unless set.
+    pub listen: Option<String>,
+    // Agents must present it when set. `qmkonnect agent` presents it too,
+    // unless given --token.
+    pub token: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Hello {
+    #[serde(default)]
+    token: Option<String>,
+}
+
+// Accept agents in the background if configured
+pub fn start(config: &RemoteConfig, verbose: bool) {
+    let Some(address) = &config.listen else {
+        return;
+    };
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not listen for agents on {}: {}", address, e);
+            return;
+        }
+    };
+    if verbose {
+        println!("Listening for agents on {}", address);
+    }
+
+    let token = config.token.clone();
+    thread::spawn(move || {
+        serve(listener, token, verbose, move |source, window_info| {
+            if let Err(e) = notifier::notify_remote(source, window_info.as_ref(), verbose) {
+                eprintln!("Error notifying QMK: {}", e);
+            }
+        })
+    });
+}
+
+// Calls on_window with each agent's windows, and with None once it is gone
+fn serve<F>(listener: TcpListener, token: Option<String>, verbose: bool, on_window: F)
+where
+    F: Fn(&str, Option<WindowInfo>) + Clone + Send + 'static,
+{
+    for stream in listener.incoming().flatten() {
+        let token = token.clone();
+        let on_window = on_window.clone();
+        thread::spawn(move || {
+            if let Err(e) = read_agent(stream, token.as_deref(), verbose, on_window) {
+                if verbose {
+                    println!("Agent dropped: {}", e);
+                }
+            }
+        });
+    }
+}
+
+fn read_agent<F>(
+    stream: TcpStream,
+    token: Option<&str>,
+    verbose: bool,
+    on_window: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(&str, Option<WindowInfo>),
+{
+    let source = format!("agent {}", stream.peer_addr()?);
+    let mut writer = stream.try_clone()?;
+    // Anyone can connect, so don't wait long for a hello that may never come
+    stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+
+    let hello: Hello = match read_line(&mut reader)? {
+        Some(line) => serde_json::from_str(&line)?,
+        None => return Ok(()),
+    };
+    if token.is_some_and(|token| !same_token(token, hello.token.as_deref())) {
+        writeln!(
+            writer,
+            "{}",
+            json!({ "ok": false, "error": "Invalid token" })
+        )?;
+        return Err(format!("{} sent an invalid token", source).into());
+    }
+    writeln!(writer, "{}", json!({ "ok": true }))?;
+    // Agents only write when focus changes
+    reader.get_ref().set_read_timeout(None)?;
+    if verbose {
+        println!("Agent connected: {}", source);
+    }
+
+    let result = loop {
+        match read_line(&mut reader) {
+            Ok(Some(line)) => match serde_json::from_str::<WindowInfo>(&line) {
+                Ok(window_info) => on_window(&source, Some(window_info)),
+                Err(e) if verbose => println!("Ignoring window from {}: {}", source, e),
+                Err(_) => {}
+            },
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e.into()),
+        }
+    };
+
+    if verbose {
+        println!("Agent disconnected: {}", source);
+    }
+    on_window(&source, None);
+    result
+}
+
+// Compares every byte whatever the guess, so timing doesn't give the token away
+fn same_token(token: &str, given: Option<&str>) -> bool {
+    let given = given.unwrap_or_default().as_bytes();
+    let mut difference = token.len() ^ given.len();
+    for (i, byte) in token.bytes().enumerate() {
+        difference |= usize::from(byte ^ given.get(i).copied().unwrap_or(0));
+    }
+    difference == 0
+}
+
+// None at the end of the stream
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.by_ref().take(MAX_LINE).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && line.len() as u64 >= MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    Ok(Some(line))
+}
+
+// "tcp://host:port", or just "host:port"
+pub fn parse_address(address: &str) -> Result<String, String> {
+    let address = match address.split_once("://") {
+        Some(("tcp", address)) => address,
+        Some((scheme, _)) => return Err(format!("Unsupported scheme '{}', use tcp://", scheme)),
+        None => address,
+    };
+    if address
+        .rsplit_once(':')
+        .is_none_or(|(host, port)| host.is_empty() || port.parse::<u16>().is_err())
+    {
+        return Err(format!(
+            "Invalid address '{}', use tcp://host:port",
+            address
+        ));
+    }
+    Ok(address.to_string())
+}
+
+// Forward every window from now on instead of sending it to a keyboard here
+pub fn start_agent(address: &str, token: Option<String>, verbose: bool) -> Result<(), String> {
+    let address = parse_address(address)?;
+    let (sender, receiver) = mpsc::channel();
+    notifier::forward_to(sender);
+    thread::spawn(move || forward(&address, token.as_deref(), receiver, verbose));
+    Ok(())
+}
+
+// Connect again whenever the other end goes away, starting with the latest window
+fn forward(address: &str, token: Option<&str>, windows: Receiver<WindowInfo>, verbose: bool) {
+    let mut latest: Option<WindowInfo> = None;
+    loop {
+        match connect(address, token) {
+            Ok(mut stream) => {
+                if verbose {
+                    println!("Forwarding windows to {}", address);
+                }
+                match send_windows(&mut stream, &windows, &mut latest) {
+                    // Nothing left to forward
+                    Ok(()) => return,
+                    Err(e) => eprintln!("Lost connection to {}: {}", address, e),
+                }
+            }
+            Err(e) => eprintln!("Could not connect to {}: {}", address, e),
+        }
+
+        thread::sleep(RETRY_INTERVAL);
+        if let Some(window_info) = windows.try_iter().last() {
+            latest = Some(window_info);
+        }
+    }
+}
+
+fn connect(address: &str, token: Option<&str>) -> Result<TcpStream, Box<dyn Error>> {
+    let mut stream = TcpStream::connect(address)?;
+    let hello = Hello {
+        token: token.map(str::to_string),
+    };
+    writeln!(stream, "{}", serde_json::to_string(&hello)?)?;
+
+    stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let line = read_line(&mut reader)?.ok_or("connection closed")?;
+    let response: Value = serde_json::from_str(&line)?;
+    if response["ok"] != json!(true) {
+        let message = response["error"].as_str().unwrap_or("refused");
+        return Err(message.into());
+    }
+    stream.set_read_timeout(None)?;
+    Ok(stream)
+}
+
+fn send_windows(
+    stream: &mut TcpStream,
+    windows: &Receiver<WindowInfo>,
+    latest: &mut Option<WindowInfo>,
+) -> io::Result<()> {
+    if let Some(window_info) = latest {
+        send_window(stream, window_info)?;
+    }
+    for window_info in windows {
+        let window_info = latest.insert(window_info);
+        send_window(stream, window_info)?;
+    }
+    Ok(())
+}
+
+fn send_window(stream: &mut TcpStream, window_info: &WindowInfo) -> io::Result<()> {
+    let mut line = serde_json::to_string(window_info)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::notifier::ContextState;
+    use crate::core::Config;
+    use std::sync::mpsc::Sender;
+    use std::sync::{Arc, Mutex};
+
+    type Event = (String, Option<WindowInfo>);
+
+    // A receiving instance on a free loopback port
+    fn listen(token: Option<&str>) -> (String, Receiver<Event>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (sender, receiver) = mpsc::channel();
+        let sender: Sender<Event> = sender;
+        let token = token.map(str::to_string);
+        thread::spawn(move || {
+            serve(listener, token, false, move |source, window_info| {
+                let _ = sender.send((source.to_string(), window_info));
+            })
+        });
+        (address, receiver)
+    }
+
+    fn recv(receiver: &Receiver<Event>) -> Event {
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    fn window(app_class: &str) -> WindowInfo {
+        let mut window_info = WindowInfo::new(app_class.to_string(), "title".to_string());
+        window_info
+            .fields
+            .insert("process".to_string(), "nvim".to_string());
+        window_info
+    }
+
+    #[test]
+    fn test_forwards_windows() {
+        let (address, events) = listen(Some("secret"));
+        let (sender, windows) = mpsc::channel();
+        let agent = thread::spawn(move || forward(&address, Some("secret"), windows, false));
+
+        sender.send(window("kitty")).unwrap();
+        sender.send(window("firefox")).unwrap();
+        let (source, window_info) = recv(&events);
+        assert!(source.starts_with("agent 127.0.0.1:"));
+        assert_eq!(window_info, Some(window("kitty")));
+        assert_eq!(recv(&events).1, Some(window("firefox")));
+
+        // The agent stopping disconnects it
+        drop(sender);
+        agent.join().unwrap();
+        assert_eq!(recv(&events), (source, None));
+    }
+
+    #[test]
+    fn test_agent_windows_become_context() {
+        // What notify_remote does to the notifier's context, on one of our own
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let context = Arc::new(Mutex::new(ContextState::default()));
+        let (sender, changes) = mpsc::channel();
+        thread::spawn({
+            let context = Arc::clone(&context);
+            move || {
+                serve(listener, None, false, move |source, window_info| {
+                    let mut context = context.lock().unwrap();
+                    let changed = match &window_info {
+                        Some(window_info) => {
+                            context.focus(window_info, Some(source));
+                            true
+                        }
+                        None => context.agent_gone(source),
+                    };
+                    let _ = sender.send(changed);
+                })
+            }
+        });
+        let recv = || changes.recv_timeout(Duration::from_secs(5)).unwrap();
+        let local = WindowInfo::new("firefox".to_string(), "Docs".to_string());
+        context.lock().unwrap().focus(&local, None);
+
+        let mut remote = window("kitty");
+        remote.title = "[tmux] work:nvim".to_string();
+        let mut agent = connect(&address, None).unwrap();
+        send_window(&mut agent, &remote).unwrap();
+        assert!(recv());
+        let (source, current, enriched) = context.lock().unwrap().current().unwrap();
+        assert!(source.unwrap().starts_with("agent 127.0.0.1:"));
+        assert_eq!(current, remote);
+        assert!(enriched);
+
+        // Enriched by the agent with its own settings, not again here
+        let config = Config {
+            titles: crate::core::title::TitleConfig {
+                builtin: true,
+                patterns: Vec::new(),
+            },
+            ..Config::default()
+        };
+        let processed = context.lock().unwrap().process(&current, enriched, &config);
+        assert_eq!(processed.unwrap().fields.get("tmux_session"), None);
+        let processed = ContextState::default().process(&current, false, &config);
+        assert_eq!(processed.unwrap().fields["tmux_session"], "work");
+
+        // Another agent going away leaves the context alone
+        drop(connect(&address, None).unwrap());
+        assert!(!recv());
+        assert_eq!(context.lock().unwrap().current().unwrap().1, remote);
+
+        // Local focus counts again once the agent that has it is gone
+        drop(agent);
+        assert!(recv());
+        let (source, current, enriched) = context.lock().unwrap().current().unwrap();
+        assert_eq!((source, current, enriched), (None, local, false));
+    }
+
+    #[test]
+    fn test_drops_silent_peers() {
+        let (address, events) = listen(None);
+        let mut silent = TcpStream::connect(&address).unwrap();
+        silent
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        // Hung up on after the hello timeout rather than held on to
+        assert_eq!(silent.read(&mut [0u8; 1]).unwrap(), 0);
+
+        // Agents past the hello may take their time
+        let mut agent = connect(&address, None).unwrap();
+        thread::sleep(HELLO_TIMEOUT * 2);
+        send_window(&mut agent, &window("code")).unwrap();
+        assert_eq!(recv(&events).1, Some(window("code")));
+    }
+
+    #[test]
+    fn test_same_token() {
+        assert!(same_token("secret", Some("secret")));
+        assert!(!same_token("secret", Some("secreT")));
+        assert!(!same_token("secret", Some("secret2")));
+        assert!(!same_token("secret", Some("")));
+        assert!(!same_token("secret", None));
+    }
+
+    #[test]
+    fn test_refuses_wrong_token() {
+        let (address, events) = listen(Some("secret"));
+
+        let error = connect(&address, Some("guess")).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid token");
+        let error = connect(&address, None).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid token");
+
+        let _accepted = connect(&address, Some("secret")).unwrap();
+        // Refused agents never got as far as connecting or sending windows
+        assert!(events.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn test_no_token_needed() {
+        let (address, events) = listen(None);
+        let mut stream = connect(&address, Some("anything")).unwrap();
+        send_window(&mut stream, &window("code")).unwrap();
+        assert_eq!(recv(&events).1, Some(window("code")));
+    }
+
+    #[test]
+    fn test_ignores_garbage() {
+        let (address, events) = listen(None);
+        let mut stream = connect(&address, None).unwrap();
+        stream.write_all(b"not a window\n").unwrap();
+        send_window(&mut stream, &window("code")).unwrap();
+        assert_eq!(recv(&events).1, Some(window("code")));
+
+        // Unending lines drop the agent
+        stream
+            .write_all(&vec![b'x'; MAX_LINE as usize + 1])
+            .unwrap();
+        assert_eq!(recv(&events).1, None);
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parse_address("tcp://10.0.2.2:7878").unwrap(),
+            "10.0.2.2:7878"
+        );
+        assert_eq!(parse_address("host.local:7878").unwrap(), "host.local:7878");
+        assert_eq!(parse_address("tcp://[::1]:7878").unwrap(), "[::1]:7878");
+        assert!(parse_address("udp://host:7878").is_err());
+        assert!(parse_address("tcp://host").is_err());
+        assert!(parse_address("tcp://:7878").is_err());
+        assert!(parse_address("host:http").is_err());
+    }
+}
@@ -0,0 +1,272 @@
+// Tell what runs inside a terminal window from its process tree, so nvim and
+// a plain shell in the same terminal can be told apart
+use crate::core::pipeline::matches_pattern;
+use crate::core::tmux;
+use crate::core::types::WindowInfo;
+use std::fs;
+use std::path::Path;
+
+// [terminal] section
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(default)]
+pub struct TerminalConfig {
+    // Class patterns of terminal emulators, e.g. "alacritty" or "*term*"
+    pub classes: Vec<String>,
+    // Send the foreground process as the title instead of only as the "process" field
+    pub rewrite_title: bool,
+    // Follow the active tmux pane and add its command as the "tmux_command" field
+    pub tmux: bool,
+    // tmux socket name (-L) or path (-S), the default server if unset
+    pub tmux_socket: Option<String>,
+    // Also ask tmux for the active pane's command this often, off unless set
+    pub tmux_poll_interval_ms: Option<u64>,
+}
+
+// Add the terminal's foreground process to the window, if it is a terminal
+pub fn enrich(window_info: &WindowInfo, config: &TerminalConfig) -> WindowInfo {
+    enrich_from(Path::new("/proc"), window_info, config)
+}
+
+fn enrich_from(proc_root: &Path, window_info: &WindowInfo, config: &TerminalConfig) -> WindowInfo {
+    let mut window_info = window_info.clone();
+    let is_terminal = config
+        .classes
+        .iter()
+        .any(|pattern| matches_pattern(pattern, &window_info.app_class));
+    if !is_terminal {
+        return window_info;
+    }
+
+    let process = window_info
+        .pid
+        .and_then(|pid| foreground_process(proc_root, pid));
+    if let Some(process) = &process {
+        if config.rewrite_title {
+            window_info.title = process.clone();
+        }
+        window_info
+            .fields
+            .insert("process".to_string(), process.clone());
+    }
+
+    // Without a process to go by, assume the terminal shows tmux
+    let in_tmux = process
+        .as_ref()
+        .is_none_or(|process| process.starts_with("tmux"));
+    if let Some(command) = tmux::active_command().filter(|_| config.tmux && in_tmux) {
+        if config.rewrite_title {
+            window_info.title = command.clone();
+        }
+        window_info
+            .fields
+            .insert("tmux_command".to_string(), command);
+    }
+    window_info
+}
+
+// The parts of /proc/<pid>/stat we need
+#[derive(Debug, PartialEq)]
+struct Stat {
+    pid: u32,
+    comm: String,
+    ppid: u32,
+    // Foreground process group of the controlling terminal, -1 without one
+    tpgid: i32,
+    start_time: u64,
+}
+
+fn read_stat(proc_root: &Path, pid: u32) -> Option<Stat> {
+    parse_stat(&fs::read_to_string(proc_root.join(pid.to_string()).join("stat")).ok()?)
+}
+
+fn parse_stat(stat: &str) -> Option<Stat> {
+    // The command name is in parentheses and may contain both spaces and parentheses
+    let (pid, rest) = stat.split_once(" (")?;
+    let (comm, rest) = rest.rsplit_once(") ")?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+
+    Some(Stat {
+        pid: pid.parse().ok()?,
+        comm: comm.to_string(),
+        ppid: fields.get(1)?.parse().ok()?,
+        tpgid: fields.get(5)?.parse().ok()?,
+        start_time: fields.get(19)?.parse().ok()?,
+    })
+}
+
+// The terminal's children are the shells of its tabs. Each shell's tty knows
+// which process group is in the foreground, whose leader is what the user
+// runs. With several tabs the most recently started one wins.
+fn foreground_process(proc_root: &Path, terminal_pid: u32) -> Option<String> {
+    let processes: Vec<Stat> = fs::read_dir(proc_root)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .filter_map(|pid| read_stat(proc_root, pid))
+        .collect();
+
+    processes
+        .iter()
+        .filter(|process| process.ppid == terminal_pid && process.tpgid > 0)
+        .filter_map(|shell| {
+            let tpgid = shell.tpgid as u32;
+            processes.iter().find(|process| process.pid == tpgid)
+        })
+        .max_by_key(|foreground| foreground.start_time)
+        .map(|foreground| foreground.comm.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A /proc with nothing but stat files
+    struct FakeProc {
+        root: PathBuf,
+    }
+
+    impl FakeProc {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "qmkonnect-proc-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            Self { root }
+        }
+
+        fn add(&self, pid: u32, comm: &str, ppid: u32, tpgid: i32, start_time: u64) {
+            let dir = self.root.join(pid.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("stat"),
+                format!(
+                    "{} ({}) S {} {} {} 34816 {} 4194560 0 0 0 0 0 0 0 0 20 0 1 0 {} 0 0",
+                    pid, comm, ppid, pid, pid, tpgid, start_time
+                ),
+            )
+            .unwrap();
+        }
+    }
+
+    impl Drop for FakeProc {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn terminal_config(rewrite_title: bool) -> TerminalConfig {
+        TerminalConfig {
+            classes: vec!["alacritty".to_string()],
+            rewrite_title,
+            ..Default::default()
+        }
+    }
+
+    fn alacritty(pid: u32) -> WindowInfo {
+        let mut window_info = WindowInfo::new("Alacritty".to_string(), "~/src".to_string());
+        window_info.pid = Some(pid);
+        window_info
+    }
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = parse_stat(
+            "4242 (tmux: server (1)) S 1 4242 4242 0 -1 4194624 0 0 0 0 0 0 0 0 20 0 1 0 98765 0 0",
+        )
+        .unwrap();
+        assert_eq!(
+            stat,
+            Stat {
+                pid: 4242,
+                comm: "tmux: server (1)".to_string(),
+                ppid: 1,
+                tpgid: -1,
+                start_time: 98765,
+            }
+        );
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    #[test]
+    fn test_foreground_process() {
+        let proc = FakeProc::new("foreground");
+        proc.add(100, "alacritty", 1, -1, 10);
+        proc.add(101, "zsh", 100, 105, 11);
+        proc.add(105, "nvim", 101, 105, 20);
+        // Background job of the same shell
+        proc.add(106, "cargo", 101, 105, 30);
+
+        let window_info = enrich_from(&proc.root, &alacritty(100), &terminal_config(false));
+        assert_eq!(window_info.fields["process"], "nvim");
+        assert_eq!(window_info.title, "~/src");
+
+        let window_info = enrich_from(&proc.root, &alacritty(100), &terminal_config(true));
+        assert_eq!(window_info.title, "nvim");
+    }
+
+    #[test]
+    fn test_idle_shell_and_newest_tab() {
+        let proc = FakeProc::new("tabs");
+        proc.add(200, "kitty", 1, -1, 10);
+        proc.add(201, "zsh", 200, 202, 11);
+        proc.add(202, "htop", 201, 202, 12);
+        // An idle shell is in the foreground of its own tty
+        proc.add(203, "bash", 200, 203, 40);
+
+        assert_eq!(
+            foreground_process(&proc.root, 200),
+            Some("bash".to_string())
+        );
+    }
+
+    #[test]
+    fn test_only_configured_terminals() {
+        let proc = FakeProc::new("other");
+        proc.add(300, "firefox", 1, -1, 10);
+        proc.add(301, "firefox", 300, -1, 11);
+
+        let mut firefox = WindowInfo::new("firefox".to_string(), "GitHub".to_string());
+        firefox.pid = Some(300);
+        assert_eq!(
+            enrich_from(&proc.root, &firefox, &terminal_config(true)),
+            firefox
+        );
+
+        // No pid from the backend, nothing to look up
+        let window_info = WindowInfo::new("Alacritty".to_string(), "~".to_string());
+        assert_eq!(
+            enrich_from(&proc.root, &window_info, &terminal_config(true)),
+            window_info
+        );
+    }
+
+    #[test]
+    fn test_tmux_command() {
+        let proc = FakeProc::new("tmux");
+        proc.add(400, "alacritty", 1, -1, 10);
+        proc.add(401, "zsh", 400, 402, 11);
+        proc.add(402, "tmux: client", 401, 402, 12);
+
+        let config = TerminalConfig {
+            tmux: true,
+            ..terminal_config(false)
+        };
+        tmux::set_active_command(Some("nvim"));
+
+        let window_info = enrich_from(&proc.root, &alacritty(400), &config);
+        assert_eq!(window_info.fields["process"], "tmux: client");
+        assert_eq!(window_info.fields["tmux_command"], "nvim");
+
+        // A terminal that runs something else isn't showing tmux
+        proc.add(403, "htop", 401, 403, 13);
+        proc.add(401, "zsh", 400, 403, 11);
+        let window_info = enrich_from(&proc.root, &alacritty(400), &config);
+        assert_eq!(window_info.fields["process"], "htop");
+        assert!(!window_info.fields.contains_key("tmux_command"));
+
+        tmux::set_active_command(None);
+    }
+}
@@ -0,0 +1,182 @@
+// Pull structured context out of window titles, e.g. the host of an SSH
+// session from "deploy@prod-db: ~/src"
+use crate::core::pipeline::matches_pattern;
+use crate::core::types::WindowInfo;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// Shells commonly title terminals user@host:path, which carries over SSH
+static USER_HOST_PATH: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?P<user>[\w.-]+)@(?P<host>[\w.-]+):\s*(?P<path>\S.*)$").unwrap());
+
+// "[tmux] session:window", as set by set-titles-string "[tmux] #S:#W"
+static TMUX_SESSION_WINDOW: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\[tmux\]\s+(?P<tmux_session>[^:\s]+):(?P<tmux_window>.+)$").unwrap()
+});
+
+// [titles] section
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(default)]
+pub struct TitleConfig {
+    // Apply the built-in extractors above to every window
+    pub builtin: bool,
+    // Checked before the built-in ones
+    pub patterns: Vec<TitlePattern>,
+}
+
+// [[titles.patterns]] entry. Named captures become window fields.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct TitlePattern {
+    pub regex: TitleRegex,
+    // Class patterns the regex applies to, every class while empty
+    #[serde(default)]
+    pub classes: Vec<String>,
+}
+
+// A regex checked when the configuration is loaded
+#[derive(Clone, Debug)]
+pub struct TitleRegex(Regex);
+
+impl<'de> serde::Deserialize<'de> for TitleRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(TitleRegex)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for TitleRegex {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+// Add the named captures of every matching extractor to the window. Fields
+// that are already set, by the backend or an earlier extractor, are kept.
+pub fn parse(window_info: &WindowInfo, config: &TitleConfig) -> WindowInfo {
+    let mut window_info = window_info.clone();
+
+    let user_patterns = config
+        .patterns
+        .iter()
+        .filter(|pattern| {
+            pattern.classes.is_empty()
+                || pattern
+                    .classes
+                    .iter()
+                    .any(|class| matches_pattern(class, &window_info.app_class))
+        })
+        .map(|pattern| &pattern.regex.0);
+    let builtin = [&*USER_HOST_PATH, &*TMUX_SESSION_WINDOW]
+        .into_iter()
+        .filter(|_| config.builtin);
+
+    let extracted: Vec<(String, String)> = user_patterns
+        .chain(builtin)
+        .filter_map(|regex| Some((regex, regex.captures(&window_info.title)?)))
+        .flat_map(|(regex, captures)| {
+            regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| {
+                    Some((name.to_string(), captures.name(name)?.as_str().to_string()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    for (name, value) in extracted {
+        window_info.fields.entry(name).or_insert(value);
+    }
+    window_info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titled(app_class: &str, title: &str) -> WindowInfo {
+        WindowInfo::new(app_class.to_string(), title.to_string())
+    }
+
+    fn builtin() -> TitleConfig {
+        TitleConfig {
+            builtin: true,
+            patterns: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_user_host_path() {
+        let window_info = parse(
+            &titled("kitty", "deploy@prod-db.example: ~/src"),
+            &builtin(),
+        );
+        assert_eq!(window_info.fields["user"], "deploy");
+        assert_eq!(window_info.fields["host"], "prod-db.example");
+        assert_eq!(window_info.fields["path"], "~/src");
+
+        let window_info = parse(&titled("kitty", "Mail from bob@example.com"), &builtin());
+        assert!(window_info.fields.is_empty());
+    }
+
+    #[test]
+    fn test_tmux_session_window() {
+        let window_info = parse(&titled("foot", "[tmux] work:nvim"), &builtin());
+        assert_eq!(window_info.fields["tmux_session"], "work");
+        assert_eq!(window_info.fields["tmux_window"], "nvim");
+    }
+
+    #[test]
+    fn test_builtin_off_by_default() {
+        let window_info = titled("kitty", "deploy@prod-db: ~");
+        assert_eq!(parse(&window_info, &TitleConfig::default()), window_info);
+    }
+
+    #[test]
+    fn test_user_patterns() {
+        let config: TitleConfig = toml::from_str(
+            r#"
+            builtin = true
+
+            [[patterns]]
+            regex = '@(?P<host>prod)-(?P<role>\w+)(?P<env> staging)?'
+            classes = ["*term*"]
+
+            [[patterns]]
+            regex = '\((?P<branch>[\w/-]+)\)$'
+            "#,
+        )
+        .unwrap();
+
+        // User patterns win over the built-in ones, optional groups that
+        // didn't match are left out
+        let window_info = parse(&titled("xterm", "root@prod-db: ~/src (main)"), &config);
+        assert_eq!(window_info.fields["host"], "prod");
+        assert_eq!(window_info.fields["role"], "db");
+        assert!(!window_info.fields.contains_key("env"));
+        assert_eq!(window_info.fields["user"], "root");
+        assert_eq!(window_info.fields["branch"], "main");
+
+        // Only for the listed classes
+        let window_info = parse(&titled("firefox", "root@prod-db: ~"), &config);
+        assert_eq!(window_info.fields["host"], "prod-db");
+        assert!(!window_info.fields.contains_key("role"));
+
+        // Fields from the backend are kept
+        let mut window_info = titled("xterm", "root@prod-db staging");
+        window_info
+            .fields
+            .insert("env".to_string(), "backend".to_string());
+        assert_eq!(parse(&window_info, &config).fields["env"], "backend");
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let error = toml::from_str::<TitleConfig>("[[patterns]]\nregex = '(unclosed'")
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("unclosed"));
+    }
+}
@@ -0,0 +1,370 @@
+// Follow the active tmux pane over a control mode client, since inside tmux the
+// terminal's foreground process is only ever tmux itself
+use crate::core::notifier;
+use crate::core::pipeline::matches_pattern;
+use crate::core::terminal::TerminalConfig;
+use once_cell::sync::Lazy;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How long to wait before attaching again when there is no tmux server
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+// Notifications after which the active pane or its command may be different
+const TRIGGERS: &[&str] = &[
+    "%session-changed",
+    "%client-session-changed",
+    "%session-window-changed",
+    "%window-pane-changed",
+    // automatic-rename follows the command running in the active pane
+    "%window-renamed",
+];
+
+const CLIENTS_QUERY: &str =
+    "list-clients -F \"#{client_control_mode} #{client_activity} #{pane_current_command}\"\n";
+
+// Command in the active pane, None while we are not attached
+static ACTIVE_COMMAND: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn active_command() -> Option<String> {
+    ACTIVE_COMMAND.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+pub fn set_active_command(command: Option<&str>) {
+    *ACTIVE_COMMAND.lock().unwrap() = command.map(str::to_string);
+}
+
+// Follow tmux in the background if enabled, attaching again whenever the server goes away
+pub fn start(config: &TerminalConfig, verbose: bool) {
+    if !config.tmux {
+        return;
+    }
+
+    let socket = config.tmux_socket.clone();
+    let poll_interval = config.tmux_poll_interval_ms.map(Duration::from_millis);
+    if verbose {
+        if let Some(poll_interval) = poll_interval {
+            println!("Polling tmux every {:?}", poll_interval);
+        }
+    }
+    thread::spawn(move || loop {
+        match ControlClient::attach(socket.as_deref(), poll_interval) {
+            Ok(mut client) => loop {
+                match client.next_command() {
+                    Ok(command) => update(command, verbose),
+                    Err(e) => {
+                        if verbose {
+                            println!("Detached from tmux: {}", e);
+                        }
+                        break;
+                    }
+                }
+            },
+            Err(e) => {
+                if verbose {
+                    println!("Could not attach to tmux: {}", e);
+                }
+            }
+        }
+
+        *ACTIVE_COMMAND.lock().unwrap() = None;
+        thread::sleep(RETRY_INTERVAL);
+    });
+}
+
+fn update(command: String, verbose: bool) {
+    {
+        let mut active = ACTIVE_COMMAND.lock().unwrap();
+        if active.as_deref() == Some(command.as_str()) {
+            return;
+        }
+        *active = Some(command.clone());
+    }
+
+    if verbose {
+        println!("Active tmux pane runs '{}'", command);
+    }
+
+    // Nothing else tells the keyboard the focused terminal changed inside
+    let terminal = crate::core::runtime_config().terminal;
+    let Some(window_info) = notifier::last_window() else {
+        return;
+    };
+    if terminal
+        .classes
+        .iter()
+        .any(|pattern| matches_pattern(pattern, &window_info.app_class))
+    {
+        if let Err(e) = notifier::refresh(verbose) {
+            eprintln!("Error notifying QMK: {}", e);
+        }
+    }
+}
+
+// A read-only tmux client in control mode, which tmux leaves out of window sizing
+struct ControlClient {
+    child: Child,
+    stdin: Arc<Mutex<ChildStdin>>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ControlClient {
+    // A socket with a slash in it is a path (-S), anything else a name (-L).
+    // Commands started inside a pane come without a notification, so with a
+    // poll interval the active pane is also asked for on a timer.
+    fn attach(socket: Option<&str>, poll_interval: Option<Duration>) -> io::Result<Self> {
+        let mut command = Command::new("tmux");
+        match socket {
+            Some(path) if path.contains('/') => command.args(["-S", path]),
+            Some(name) => command.args(["-L", name]),
+            None => &mut command,
+        };
+        let mut child = command
+            .args(["-C", "attach-session", "-r"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::other("no stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("no stdout"))?;
+        let client = Self {
+            child,
+            stdin: Arc::new(Mutex::new(stdin)),
+            stdout: BufReader::new(stdout),
+        };
+
+        // We only care about notifications, not about what the panes print.
+        // Older tmux rejects the flag, which only costs some parsing.
+        send(&client.stdin, "refresh-client -f no-output\n")?;
+
+        // Stops once tmux is gone and the pipe breaks
+        if let Some(poll_interval) = poll_interval {
+            let stdin = Arc::clone(&client.stdin);
+            thread::spawn(move || loop {
+                thread::sleep(poll_interval);
+                if send(&stdin, CLIENTS_QUERY).is_err() {
+                    break;
+                }
+            });
+        }
+
+        Ok(client)
+    }
+
+    // Block until tmux reports the active pane's command, whether or not it changed
+    fn next_command(&mut self) -> io::Result<String> {
+        let mut block: Option<Vec<String>> = None;
+        let mut line = Vec::new();
+
+        loop {
+            line.clear();
+            if self.stdout.read_until(b'\n', &mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "tmux closed the connection",
+                ));
+            }
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+
+            if line.starts_with("%begin") {
+                block = Some(Vec::new());
+            } else if line.starts_with("%end") || line.starts_with("%error") {
+                if let Some(command) = block.take().and_then(|lines| parse_clients(&lines)) {
+                    return Ok(command);
+                }
+            } else if let Some(lines) = block.as_mut() {
+                lines.push(line);
+            } else if TRIGGERS
+                .iter()
+                .any(|trigger| line.split(' ').next() == Some(trigger))
+            {
+                send(&self.stdin, CLIENTS_QUERY)?;
+            }
+        }
+    }
+}
+
+impl Drop for ControlClient {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn send(stdin: &Mutex<ChildStdin>, command: &str) -> io::Result<()> {
+    let mut stdin = stdin.lock().unwrap();
+    stdin.write_all(command.as_bytes())?;
+    stdin.flush()
+}
+
+// Pick the pane of the most recently used client. Our own control client only
+// counts when nobody else is attached.
+fn parse_clients(lines: &[String]) -> Option<String> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let control = parts.next()? == "1";
+            let activity: u64 = parts.next()?.parse().ok()?;
+            Some((!control, activity, parts.next().unwrap_or("")))
+        })
+        .max_by_key(|(user, activity, _)| (*user, *activity))
+        .map(|(_, _, command)| command.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    // A tmux server on its own socket, so tests never touch the user's sessions
+    struct TestServer {
+        socket: String,
+    }
+
+    impl TestServer {
+        fn start(name: &str, command: &str) -> Option<Self> {
+            let server = Self {
+                socket: format!("qmkonnect-test-{}-{}", name, std::process::id()),
+            };
+            let started = server
+                .tmux(&[
+                    "-f",
+                    "/dev/null",
+                    "new-session",
+                    "-d",
+                    "-s",
+                    "test",
+                    command,
+                ])
+                .ok()?;
+            started.then_some(server)
+        }
+
+        fn tmux(&self, args: &[&str]) -> io::Result<bool> {
+            Command::new("tmux")
+                .args(["-L", &self.socket])
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|status| status.success())
+        }
+
+        fn pane_commands(&self) -> Vec<String> {
+            let output = Command::new("tmux")
+                .args(["-L", &self.socket, "list-panes", "-t", "test"])
+                .args(["-F", "#{pane_current_command}"])
+                .output()
+                .unwrap();
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            let _ = self.tmux(&["kill-server"]);
+        }
+    }
+
+    // Commands reported by a client attached to the server
+    fn follow(server: &TestServer, poll_interval: Option<Duration>) -> mpsc::Receiver<String> {
+        let mut client = ControlClient::attach(Some(&server.socket), poll_interval).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(command) = client.next_command() {
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    fn wait_for(receiver: &mpsc::Receiver<String>, expected: &str) {
+        loop {
+            let command = receiver
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap_or_else(|_| panic!("tmux never reported '{}'", expected));
+            if command == expected {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_clients() {
+        let lines = vec![
+            "1 1700000300 zsh".to_string(),
+            "0 1700000100 htop".to_string(),
+            "0 1700000200 nvim".to_string(),
+        ];
+        assert_eq!(parse_clients(&lines), Some("nvim".to_string()));
+
+        // Only our own client is attached
+        assert_eq!(
+            parse_clients(&["1 1700000300 zsh".to_string()]),
+            Some("zsh".to_string())
+        );
+        assert_eq!(parse_clients(&[]), None);
+        assert_eq!(parse_clients(&["no current client".to_string()]), None);
+    }
+
+    #[test]
+    fn test_follows_pane_switches() {
+        let Some(server) = TestServer::start("switches", "sleep 600") else {
+            eprintln!("tmux not available, skipping");
+            return;
+        };
+        assert!(server.tmux(&["split-window", "-t", "test", "cat"]).unwrap());
+        assert!(server.tmux(&["select-pane", "-t", "test:0.0"]).unwrap());
+        // Without polling only switching tells us, so both have to run already
+        while server.pane_commands() != ["sleep", "cat"] {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let receiver = follow(&server, None);
+        wait_for(&receiver, "sleep");
+        assert!(server.tmux(&["select-pane", "-t", "test:0.1"]).unwrap());
+        wait_for(&receiver, "cat");
+        assert!(server.tmux(&["select-pane", "-t", "test:0.0"]).unwrap());
+        wait_for(&receiver, "sleep");
+    }
+
+    #[test]
+    fn test_follows_active_pane() {
+        let Some(server) = TestServer::start("polling", "sleep 600") else {
+            eprintln!("tmux not available, skipping");
+            return;
+        };
+
+        // A new pane starts its command after tmux tells us about it
+        let receiver = follow(&server, Some(Duration::from_millis(100)));
+        wait_for(&receiver, "sleep");
+
+        // Splitting focuses the new pane
+        assert!(server.tmux(&["split-window", "-t", "test", "cat"]).unwrap());
+        wait_for(&receiver, "cat");
+
+        assert!(server.tmux(&["select-pane", "-t", "test:0.0"]).unwrap());
+        wait_for(&receiver, "sleep");
+
+        // So does a new window
+        assert!(server.tmux(&["new-window", "-t", "test", "cat"]).unwrap());
+        wait_for(&receiver, "cat");
+    }
+}
@@ -1,15 +1,51 @@
-#[derive(Debug, PartialEq)]
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
 pub struct WindowInfo {
     pub app_class: String,
     pub title: String,
+    // Only set by backends that can tell
+    pub fullscreen: bool,
+    pub floating: bool,
+    // A dialog or other window that belongs to another one
+    pub transient: bool,
+    pub pid: Option<u32>,
+    // Extra context such as "workspace", only sent when listed in [encoder] fields
+    pub fields: BTreeMap<String, String>,
 }
 
 impl WindowInfo {
     pub fn new(app_class: String, title: String) -> Self {
-        Self { app_class, title }
+        Self {
+            app_class,
+            title,
+            fullscreen: false,
+            floating: false,
+            transient: false,
+            pid: None,
+            fields: BTreeMap::new(),
+        }
     }
 }
 
+// Host state other than the focused window that firmware may react to.
+// Only reported on Linux so far, apart from editor modes
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub enum HostEvent {
+    // Hyprland keybind submap, empty when back in the global map
+    #[cfg_attr(not(feature = "hyprland"), allow(dead_code))]
+    Submap(String),
+    // Name of the active keyboard layout, e.g. "English (US)"
+    Layout(String),
+    // Class of a window that asks for attention
+    Attention(String),
+    // The focused window entered or left fullscreen
+    Fullscreen(bool),
+    // Editor mode such as "insert" or "normal", empty once the editor is closed
+    Mode(String),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,342 @@
+// Processed windows POSTed as JSON to URLs of your choosing, for tooling that
+// only speaks HTTP. Built with the webhook feature.
+#[cfg(feature = "webhook")]
+use crate::core::notifier::{self, Notifier};
+#[cfg(feature = "webhook")]
+use crate::core::types::WindowInfo;
+use std::collections::BTreeMap;
+#[cfg(feature = "webhook")]
+use std::error::Error;
+#[cfg(feature = "webhook")]
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "webhook")]
+use std::thread;
+#[cfg(feature = "webhook")]
+use std::time::{Duration, Instant};
+
+// Wait before the first retry, doubled for every one after
+#[cfg(all(feature = "webhook", not(test)))]
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+#[cfg(all(feature = "webhook", test))]
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+// [[webhooks]] entries
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Webhook {
+    pub url: String,
+    // Extra request headers, e.g. Authorization
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // Least time between requests. Windows come debounced already, so this
+    // only limits the rate, skipping to the latest window while it holds.
+    #[serde(default = "default_min_interval_ms")]
+    pub min_interval_ms: u64,
+    // Tries after the first, for network errors and 429 or 5xx responses
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_min_interval_ms() -> u64 {
+    1000
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+// Hands windows to a thread of its own, which does the waiting and the requests
+#[cfg(feature = "webhook")]
+pub struct WebhookSink {
+    windows: Sender<WindowInfo>,
+}
+
+#[cfg(feature = "webhook")]
+impl Notifier for WebhookSink {
+    // Host events and raw messages only mean something to the keyboard
+    fn notify(&self, _message: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    fn notify_window(
+        &self,
+        window_info: &WindowInfo,
+        _message: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.windows
+            .send(window_info.clone())
+            .map_err(|_| "Webhook stopped".into())
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl WebhookSink {
+    fn start(webhook: Webhook, verbose: bool) -> Result<Self, String> {
+        if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+            return Err(format!("Invalid webhook URL '{}'", webhook.url));
+        }
+        let (windows, receiver) = mpsc::channel();
+        thread::spawn(move || deliver(&webhook, receiver, verbose));
+        Ok(Self { windows })
+    }
+}
+
+#[cfg(feature = "webhook")]
+enum Failure {
+    Retry(String),
+    GiveUp(String),
+}
+
+// Post each window, keeping to the interval. Anything that queued up while
+// waiting is skipped for the latest window.
+#[cfg(feature = "webhook")]
+fn deliver(webhook: &Webhook, windows: Receiver<WindowInfo>, verbose: bool) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_millis(webhook.timeout_ms))
+        .build();
+    let min_interval = Duration::from_millis(webhook.min_interval_ms);
+    let mut last_request: Option<Instant> = None;
+
+    while let Ok(window_info) = windows.recv() {
+        if let Some(last_request) = last_request {
+            thread::sleep((last_request + min_interval).saturating_duration_since(Instant::now()));
+        }
+        let mut window_info = windows.try_iter().last().unwrap_or(window_info);
+
+        let mut retries = 0;
+        loop {
+            last_request = Some(Instant::now());
+            let error = match post(&agent, webhook, &window_info) {
+                Ok(()) => {
+                    if verbose {
+                        println!("Posted {} to {}", window_info.app_class, webhook.url);
+                    }
+                    break;
+                }
+                Err(Failure::Retry(error)) if retries < webhook.retries => error,
+                Err(Failure::Retry(error) | Failure::GiveUp(error)) => {
+                    eprintln!("Could not post to {}: {}", webhook.url, error);
+                    break;
+                }
+            };
+            if verbose {
+                println!("Retrying {}: {}", webhook.url, error);
+            }
+            thread::sleep((RETRY_DELAY * 2u32.pow(retries)).max(min_interval));
+            retries += 1;
+            // A newer window takes the place of the one that failed
+            if let Some(newer) = windows.try_iter().last() {
+                window_info = newer;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "webhook")]
+fn post(agent: &ureq::Agent, webhook: &Webhook, window_info: &WindowInfo) -> Result<(), Failure> {
+    let body = serde_json::to_string(window_info).map_err(|e| Failure::GiveUp(e.to_string()))?;
+    let mut request = agent
+        .post(&webhook.url)
+        .set("Content-Type", "application/json");
+    for (name, value) in &webhook.headers {
+        request = request.set(name, value);
+    }
+    match request.send_string(&body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, _)) if status == 429 || status >= 500 => {
+            Err(Failure::Retry(format!("HTTP {}", status)))
+        }
+        Err(ureq::Error::Status(status, _)) => Err(Failure::GiveUp(format!("HTTP {}", status))),
+        Err(e) => Err(Failure::Retry(e.to_string())),
+    }
+}
+
+// Post every window the keyboard is sent to each configured URL
+#[cfg(feature = "webhook")]
+pub fn start(webhooks: &[Webhook], verbose: bool) {
+    for webhook in webhooks {
+        match WebhookSink::start(webhook.clone(), verbose) {
+            Ok(sink) => notifier::add_sink(Box::new(sink)),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+#[cfg(not(feature = "webhook"))]
+pub fn start(webhooks: &[Webhook], _verbose: bool) {
+    if !webhooks.is_empty() {
+        eprintln!("Built without webhook support, ignoring [[webhooks]]");
+    }
+}
+
+#[cfg(all(test, feature = "webhook"))]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    // A request the HTTP stand-in got
+    struct Received {
+        path: String,
+        // Lowercase names
+        headers: BTreeMap<String, String>,
+        window: WindowInfo,
+        at: Instant,
+    }
+
+    // Answers with the given statuses in turn, then 200
+    fn server(statuses: &[u16]) -> (String, Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let mut statuses: VecDeque<u16> = statuses.iter().copied().collect();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap().to_string();
+
+                let mut headers = BTreeMap::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.to_string())
+                        }
+                        None => break,
+                    };
+                }
+                let mut body = vec![0u8; headers["content-length"].parse().unwrap()];
+                reader.read_exact(&mut body).unwrap();
+
+                let status = statuses.pop_front().unwrap_or(200);
+                write!(
+                    &stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                let _ = sender.send(Received {
+                    path,
+                    headers,
+                    window: serde_json::from_slice(&body).unwrap(),
+                    at: Instant::now(),
+                });
+            }
+        });
+        (url, receiver)
+    }
+
+    fn webhook(url: &str) -> Webhook {
+        toml::from_str(&format!("url = \"{}\"\nmin_interval_ms = 0", url)).unwrap()
+    }
+
+    fn window(app_class: &str) -> WindowInfo {
+        WindowInfo::new(app_class.to_string(), "title".to_string())
+    }
+
+    fn recv(received: &Receiver<Received>) -> Received {
+        received.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    fn nothing_more(received: &Receiver<Received>) -> bool {
+        received.recv_timeout(Duration::from_millis(300)).is_err()
+    }
+
+    #[test]
+    fn test_config() {
+        let defaults: Webhook = toml::from_str("url = \"https://example.com/hook\"").unwrap();
+        assert_eq!(defaults.min_interval_ms, 1000);
+        assert_eq!(defaults.retries, 3);
+        assert_eq!(defaults.timeout_ms, 5000);
+
+        let error = WebhookSink::start(webhook("example.com/hook"), false).err();
+        assert_eq!(error.unwrap(), "Invalid webhook URL 'example.com/hook'");
+    }
+
+    #[test]
+    fn test_posts_windows() {
+        let (url, received) = server(&[]);
+        let mut webhook = webhook(&url);
+        webhook
+            .headers
+            .insert("Authorization".to_string(), "Bearer secret".to_string());
+        let sink = WebhookSink::start(webhook, false).unwrap();
+
+        let mut window_info = window("kitty");
+        window_info
+            .fields
+            .insert("tmux_command".to_string(), "nvim".to_string());
+        sink.notify_window(&window_info, String::new()).unwrap();
+        let request = recv(&received);
+        assert_eq!(request.path, "/hook");
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(request.headers["authorization"], "Bearer secret");
+        assert_eq!(request.window, window_info);
+
+        // Not for the keyboard's messages
+        sink.notify("layout".to_string()).unwrap();
+        assert!(nothing_more(&received));
+    }
+
+    #[test]
+    fn test_retries() {
+        let (url, received) = server(&[503, 429, 404]);
+        let sink = WebhookSink::start(webhook(&url), false).unwrap();
+
+        sink.notify_window(&window("kitty"), String::new()).unwrap();
+        for _ in 0..3 {
+            assert_eq!(recv(&received).window, window("kitty"));
+        }
+        // Not found is not worth trying again
+        assert!(nothing_more(&received));
+
+        // Nor is anything after running out of retries
+        let (url, received) = server(&[500, 500, 500]);
+        let sink = WebhookSink::start(
+            Webhook {
+                retries: 2,
+                ..webhook(&url)
+            },
+            false,
+        )
+        .unwrap();
+        sink.notify_window(&window("kitty"), String::new()).unwrap();
+        for _ in 0..3 {
+            recv(&received);
+        }
+        assert!(nothing_more(&received));
+    }
+
+    #[test]
+    fn test_coalesces() {
+        let (url, received) = server(&[]);
+        let sink = WebhookSink::start(
+            Webhook {
+                min_interval_ms: 300,
+                ..webhook(&url)
+            },
+            false,
+        )
+        .unwrap();
+
+        sink.notify_window(&window("kitty"), String::new()).unwrap();
+        let first = recv(&received);
+        // Both come within the interval, only the latest is posted
+        sink.notify_window(&window("code"), String::new()).unwrap();
+        sink.notify_window(&window("firefox"), String::new())
+            .unwrap();
+        let second = recv(&received);
+        assert_eq!(second.window, window("firefox"));
+        assert!(second.at.duration_since(first.at) >= Duration::from_millis(250));
+        assert!(nothing_more(&received));
+    }
+}
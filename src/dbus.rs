@@ -0,0 +1,406 @@
+#![cfg(all(target_os = "linux", feature = "dbus"))]
+// io.github.Qmkonnect1 on the session bus, for desktop tooling that speaks
+// D-Bus rather than the control socket
+use crate::core;
+use crate::core::notifier::{self, WindowEvent};
+use crate::core::types::WindowInfo;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use zbus::blocking::{connection, Connection};
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface};
+
+const BUS_NAME: &str = "io.github.Qmkonnect1";
+const OBJECT_PATH: &str = "/io/github/Qmkonnect1";
+
+// Usage page of the QMK raw HID interface we talk to
+const RAW_HID_USAGE_PAGE: u16 = 0xFF60;
+
+// Class, title and fields, the same as the WindowChanged signal
+type Window = (String, String, HashMap<String, String>);
+
+// What the methods act on, the notifier outside of tests
+trait Daemon: Send + Sync {
+    fn set_paused(&self, paused: bool, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>>;
+    fn is_paused(&self) -> bool;
+    fn reload(&self, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>>;
+    fn send_raw(&self, message: String, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+struct NotifierDaemon;
+
+impl Daemon for NotifierDaemon {
+    fn set_paused(&self, paused: bool, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        notifier::set_paused(paused, verbose)
+    }
+
+    fn is_paused(&self) -> bool {
+        notifier::is_paused()
+    }
+
+    fn reload(&self, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        core::reload_runtime_config()
+            .map_err(|e| e.to_string().into())
+            .and_then(|()| notifier::refresh(verbose))
+    }
+
+    fn send_raw(&self, message: String, verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        notifier::send_raw(message, verbose)
+    }
+}
+
+struct Service {
+    // Last window the keyboard context was worked out from
+    window: Option<WindowInfo>,
+    daemon: Box<dyn Daemon>,
+    verbose: bool,
+}
+
+#[interface(name = "io.github.Qmkonnect1")]
+impl Service {
+    fn pause(&self) -> fdo::Result<()> {
+        self.daemon.set_paused(true, self.verbose).map_err(failed)
+    }
+
+    // Sends the current window right away
+    fn resume(&self) -> fdo::Result<()> {
+        self.daemon.set_paused(false, self.verbose).map_err(failed)
+    }
+
+    fn reload(&self) -> fdo::Result<()> {
+        self.daemon.reload(self.verbose).map_err(failed)
+    }
+
+    // Send a message to the keyboard as is, paused or not
+    fn send(&self, message: String) -> fdo::Result<()> {
+        self.daemon.send_raw(message, self.verbose).map_err(failed)
+    }
+
+    // Empty before the first window
+    #[zbus(property)]
+    fn active_window(&self) -> Window {
+        self.window.as_ref().map(window).unwrap_or_default()
+    }
+
+    // Vendor ID, product ID and name of every keyboard with the configured IDs
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn devices(&self) -> Vec<(u16, u16, String)> {
+        connected_devices()
+    }
+
+    #[zbus(property)]
+    fn paused(&self) -> bool {
+        self.daemon.is_paused()
+    }
+
+    #[zbus(signal)]
+    async fn window_changed(
+        emitter: &SignalEmitter<'_>,
+        app_class: &str,
+        title: &str,
+        fields: HashMap<String, String>,
+    ) -> zbus::Result<()>;
+}
+
+fn failed(e: impl Display) -> fdo::Error {
+    fdo::Error::Failed(e.to_string())
+}
+
+fn window(window_info: &WindowInfo) -> Window {
+    (
+        window_info.app_class.clone(),
+        window_info.title.clone(),
+        window_info.fields.clone().into_iter().collect(),
+    )
+}
+
+fn connected_devices() -> Vec<(u16, u16, String)> {
+    let config = core::runtime_config();
+    let Ok(api) = hidapi::HidApi::new() else {
+        return Vec::new();
+    };
+    api.device_list()
+        .filter(|device| {
+            device.vendor_id() == config.vendor_id
+                && device.product_id() == config.product_id
+                && device.usage_page() == RAW_HID_USAGE_PAGE
+        })
+        .map(|device| {
+            (
+                device.vendor_id(),
+                device.product_id(),
+                device.product_string().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
+
+// Export the service on the session bus for as long as we run
+pub fn start(verbose: bool) {
+    let builder = connection::Builder::session();
+    let connection =
+        match builder.and_then(|builder| serve(builder, Box::new(NotifierDaemon), verbose)) {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Could not register {} on the session bus: {}", BUS_NAME, e);
+                return;
+            }
+        };
+    if verbose {
+        println!("Registered {} on the session bus", BUS_NAME);
+    }
+
+    let (events, pauses) = (notifier::subscribe(), notifier::subscribe_paused());
+    thread::spawn({
+        let connection = connection.clone();
+        move || {
+            if let Err(e) = emit_windows(&connection, events) {
+                eprintln!("D-Bus service stopped: {}", e);
+            }
+        }
+    });
+    thread::spawn(move || {
+        if let Err(e) = emit_paused(&connection, pauses) {
+            eprintln!("D-Bus service stopped: {}", e);
+        }
+    });
+}
+
+fn serve(
+    builder: connection::Builder,
+    daemon: Box<dyn Daemon>,
+    verbose: bool,
+) -> zbus::Result<Connection> {
+    let service = Service {
+        window: notifier::last_window(),
+        daemon,
+        verbose,
+    };
+    builder
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, service)?
+        .build()
+}
+
+// Signal window changes as the notifier sees them
+fn emit_windows(connection: &Connection, events: Receiver<WindowEvent>) -> zbus::Result<()> {
+    let service = connection
+        .object_server()
+        .interface::<_, Service>(OBJECT_PATH)?;
+    let emitter = service.signal_emitter();
+
+    for event in events {
+        let mut current = service.get_mut();
+        // Refreshes report the same window again
+        if current.window.as_ref() != Some(&event.window) {
+            let (app_class, title, fields) = window(&event.window);
+            current.window = Some(event.window);
+            zbus::block_on(current.active_window_changed(emitter))?;
+            zbus::block_on(Service::window_changed(emitter, &app_class, &title, fields))?;
+        }
+    }
+    Ok(())
+}
+
+// Signal pausing and resuming, whether over D-Bus, the control socket or SIGUSR1
+fn emit_paused(connection: &Connection, pauses: Receiver<bool>) -> zbus::Result<()> {
+    let service = connection
+        .object_server()
+        .interface::<_, Service>(OBJECT_PATH)?;
+    for _ in pauses {
+        zbus::block_on(service.get().paused_changed(service.signal_emitter()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platforms::dbus_callback::PrivateBus;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::{Arc, Mutex};
+    use zbus::blocking::Proxy;
+    use zbus::zvariant::OwnedValue;
+
+    // Stands in for the notifier, telling pauses the way it does
+    #[derive(Default)]
+    struct MockState {
+        paused: AtomicBool,
+        reloads: AtomicUsize,
+        sent: Mutex<Vec<String>>,
+    }
+
+    struct MockDaemon {
+        state: Arc<MockState>,
+        pauses: Sender<bool>,
+    }
+
+    impl Daemon for MockDaemon {
+        fn set_paused(
+            &self,
+            paused: bool,
+            _verbose: bool,
+        ) -> Result<(), Box<dyn Error + Send + Sync>> {
+            if self.state.paused.swap(paused, Ordering::SeqCst) != paused {
+                let _ = self.pauses.send(paused);
+            }
+            Ok(())
+        }
+
+        fn is_paused(&self) -> bool {
+            self.state.paused.load(Ordering::SeqCst)
+        }
+
+        fn reload(&self, _verbose: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.state.reloads.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn send_raw(
+            &self,
+            message: String,
+            _verbose: bool,
+        ) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.state.sent.lock().unwrap().push(message);
+            Ok(())
+        }
+    }
+
+    // The service on the bus with a mock behind it, and its pause notifications
+    fn mock_service(bus: &PrivateBus) -> (Connection, Arc<MockState>, Receiver<bool>) {
+        let state = Arc::new(MockState::default());
+        let (pauses, paused) = mpsc::channel();
+        let daemon = MockDaemon {
+            state: Arc::clone(&state),
+            pauses,
+        };
+        let service = serve(bus.builder(), Box::new(daemon), false).unwrap();
+        (service, state, paused)
+    }
+
+    fn proxy(connection: &Connection) -> Proxy<'static> {
+        zbus::blocking::proxy::Builder::new(connection)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .interface(BUS_NAME)
+            .unwrap()
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .unwrap()
+    }
+
+    fn event(window_info: &WindowInfo) -> WindowEvent {
+        WindowEvent {
+            window: window_info.clone(),
+            source: None,
+            processed: Some(window_info.clone()),
+            message: None,
+            outcome: notifier::Outcome::Sent,
+        }
+    }
+
+    #[test]
+    fn test_properties() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let (_service, _state, _paused) = mock_service(&bus);
+        let client = bus.builder().build().unwrap();
+        let proxy = proxy(&client);
+
+        assert!(!proxy.get_property::<bool>("Paused").unwrap());
+        // No keyboard in here
+        assert!(proxy
+            .get_property::<Vec<(u16, u16, String)>>("Devices")
+            .unwrap()
+            .is_empty());
+        proxy.get_property::<Window>("ActiveWindow").unwrap();
+    }
+
+    #[test]
+    fn test_window_changed() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let (service, _state, _paused) = mock_service(&bus);
+        let client = bus.builder().build().unwrap();
+        let proxy = proxy(&client);
+        let mut signals = proxy.receive_signal("WindowChanged").unwrap();
+        let (sender, events) = mpsc::sync_channel(4);
+        thread::spawn(move || emit_windows(&service, events));
+
+        let mut window_info = WindowInfo::new("DBusApp".to_string(), "Inbox".to_string());
+        window_info
+            .fields
+            .insert("workspace".to_string(), "2".to_string());
+        sender.send(event(&window_info)).unwrap();
+        // The same window again, e.g. from a refresh, is not a change
+        sender.send(event(&window_info)).unwrap();
+        let firefox = WindowInfo::new("firefox".to_string(), String::new());
+        sender.send(event(&firefox)).unwrap();
+
+        let mut next = || {
+            signals
+                .next()
+                .unwrap()
+                .body()
+                .deserialize::<Window>()
+                .unwrap()
+        };
+        assert_eq!(next(), window(&window_info));
+        assert_eq!(next(), window(&firefox));
+        assert_eq!(
+            proxy.get_property::<Window>("ActiveWindow").unwrap(),
+            window(&firefox)
+        );
+    }
+
+    #[test]
+    fn test_methods() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let (service, state, paused) = mock_service(&bus);
+        let client = bus.builder().build().unwrap();
+        let proxy = proxy(&client);
+        let properties = Proxy::new(
+            &client,
+            BUS_NAME,
+            OBJECT_PATH,
+            "org.freedesktop.DBus.Properties",
+        )
+        .unwrap();
+        let mut changes = properties.receive_signal("PropertiesChanged").unwrap();
+        thread::spawn(move || emit_paused(&service, paused));
+
+        // Every pause or resume is signalled, with the value it changed to
+        let mut next_paused = || {
+            let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                changes.next().unwrap().body().deserialize().unwrap();
+            bool::try_from(&changed["Paused"]).unwrap()
+        };
+        proxy.call_method("Pause", &()).unwrap();
+        assert!(next_paused());
+        assert!(proxy.get_property::<bool>("Paused").unwrap());
+        proxy.call_method("Resume", &()).unwrap();
+        assert!(!next_paused());
+        assert!(!proxy.get_property::<bool>("Paused").unwrap());
+
+        proxy.call_method("Reload", &()).unwrap();
+        assert_eq!(state.reloads.load(Ordering::SeqCst), 1);
+        proxy.call_method("Send", &("layout",)).unwrap();
+        assert_eq!(*state.sent.lock().unwrap(), ["layout"]);
+
+        let error = proxy.call_method("Explode", &()).unwrap_err();
+        assert!(error.to_string().contains("Explode"));
+    }
+}
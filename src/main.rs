@@ -1,6 +1,9 @@
 #![cfg_attr(target_os = "windows", windows_subsystem = "windows")]
 
+#[cfg(unix)]
+mod control;
 mod core;
+mod dbus;
 mod platforms;
 mod runners;
 mod tray;
@@ -65,6 +68,45 @@ fn run() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
     let verbose = args.iter().any(|arg| arg == "-v" || arg == "--verbose");
 
+    // A command is the first argument, only -v may come before it. Anything
+    // after it is the command's own.
+    let mut rest = args
+        .iter()
+        .skip(1)
+        .skip_while(|arg| *arg == "-v" || *arg == "--verbose");
+    let command = rest.next().map(String::as_str);
+    let command_args: Vec<String> = rest.cloned().collect();
+
+    // Talk to the running daemon
+    #[cfg(unix)]
+    if command == Some("ctl") {
+        return run_ctl(&command_args);
+    }
+
+    // Pin a context on the running daemon
+    #[cfg(unix)]
+    if command == Some("override") {
+        return run_override(&command_args);
+    }
+
+    // Stand in for focus on behalf of another tool
+    #[cfg(unix)]
+    if command == Some("push") {
+        return run_push(&command_args);
+    }
+
+    // Started by the browser to pass the active tab on to the daemon
+    #[cfg(unix)]
+    if command == Some("native-host") {
+        return core::browser::run_native_host();
+    }
+
+    // Install the GNOME Shell extension that reports focus changes to us
+    #[cfg(all(target_os = "linux", feature = "gnome"))]
+    if command == Some("install-gnome-extension") {
+        return platforms::install_gnome_extension(verbose);
+    }
+
     // Check for help
     if args.iter().any(|arg| arg == "-h" || arg == "--help") {
         print_help();
@@ -81,6 +123,36 @@ fn run() -> Result<(), Box<dyn Error>> {
         return reload_config(verbose);
     }
 
+    // Settings used by the monitors and the notifier while running
+    core::load_runtime_config(verbose);
+    core::tmux::start(&core::runtime_config().terminal, verbose);
+    #[cfg(unix)]
+    core::editor::start(verbose);
+    #[cfg(unix)]
+    core::browser::start(&core::runtime_config().browser, verbose);
+    #[cfg(all(target_os = "linux", feature = "dbus"))]
+    dbus::start(verbose);
+    core::hooks::start(verbose);
+    core::mqtt::start(&core::runtime_config().mqtt, verbose);
+    core::webhook::start(&core::runtime_config().webhooks, verbose);
+
+    // Forward windows to the instance the keyboard is plugged into, or accept them
+    let remote = core::runtime_config().remote;
+    if command == Some("agent") {
+        let value = |flag: &str| {
+            command_args
+                .iter()
+                .position(|arg| arg == flag)
+                .and_then(|index| command_args.get(index + 1))
+        };
+        let address = value("--forward")
+            .ok_or("Usage: qmkonnect agent --forward tcp://<host>:<port> [--token <token>]")?;
+        let token = value("--token").cloned().or(remote.token);
+        core::remote::start_agent(address, token, verbose)?;
+    } else {
+        core::remote::start(&remote, verbose);
+    }
+
     // Use platform-specific runner
     let mut runner = runners::create_runner(verbose)?;
     runner.run(&args)
@@ -88,7 +160,7 @@ fn run() -> Result<(), Box<dyn Error>> {
 
 fn print_help() {
     println!("QMKonnect v{}", env!("CARGO_PKG_VERSION"));
-    println!("Usage: qmkonnect [OPTIONS]");
+    println!("Usage: qmkonnect [OPTIONS]\n       qmkonnect [-v] <COMMAND> [ARGS]");
     println!("\nOptions:");
     println!("  -h, --help     Display this help message");
     println!("  -v, --verbose  Enable verbose logging");
@@ -96,6 +168,42 @@ fn print_help() {
     println!("  -r, --reload   Reload configuration and update system files");
     println!("  -l, --list     List supported platforms");
 
+    #[cfg(all(target_os = "linux", feature = "gnome"))]
+    {
+        println!("\nLinux Commands:");
+        println!("  install-gnome-extension  Install the GNOME Shell extension used on GNOME");
+    }
+
+    #[cfg(unix)]
+    {
+        println!("\nControl Commands (for the running daemon):");
+        println!("  ctl status               Show whether it runs and on which platform");
+        println!("  ctl current              Show the last window and the message sent for it");
+        println!("  ctl pause                Stop sending to the keyboard");
+        println!("  ctl resume               Send to the keyboard again");
+        println!("  ctl toggle               Pause or resume");
+        println!("  ctl reload               Reload the configuration file");
+        println!("  ctl send <message>       Send a message to the keyboard as is");
+        println!("  ctl subscribe            Print every window event as a JSON line");
+        println!("  override --class <class> [--title <title>] [--for <duration>]");
+        println!("                           Send this context regardless of focus, e.g. for 20m");
+        println!("  override --clear         Follow focus again");
+        println!("  push --class <class> [--title <title>] [--priority <n>] [--for <duration>]");
+        println!("       [--source <name>]   Stand in for the focused window, for 60s by default");
+        println!("  push --clear [--source <name>]");
+        println!("                           End what the source pushed");
+    }
+
+    println!("\nRemote Commands:");
+    println!("  agent --forward tcp://<host>:<port> [--token <token>]");
+    println!("                           Forward windows to the instance the keyboard is on");
+
+    #[cfg(unix)]
+    {
+        println!("\nBrowser Commands:");
+        println!("  native-host              Native messaging host for the browser extension");
+    }
+
     #[cfg(target_os = "windows")]
     {
         println!("\nWindows Options:");
@@ -114,6 +222,178 @@ fn print_help() {
 
 
 
+#[cfg(unix)]
+fn run_ctl(args: &[String]) -> Result<(), Box<dyn Error>> {
+    use control::Request;
+    const USAGE: &str =
+        "Usage: qmkonnect ctl <status|current|pause|resume|toggle|reload|send|subscribe>";
+
+    let mut args = args
+        .iter()
+        .filter(|arg| *arg != "-v" && *arg != "--verbose");
+    let request = match args.next().map(String::as_str) {
+        Some("status") => Request::Status,
+        Some("current") => Request::Current,
+        Some("pause") => Request::Pause,
+        Some("resume") => Request::Resume,
+        Some("toggle") => Request::Toggle,
+        Some("reload") => Request::Reload,
+        Some("subscribe") => {
+            // One JSON line per window, as it comes
+            for line in control::subscribe()? {
+                println!("{}", line?);
+            }
+            return Ok(());
+        }
+        Some("send") => Request::Send {
+            message: args
+                .next()
+                .ok_or("Usage: qmkonnect ctl send <message>")?
+                .clone(),
+        },
+        Some(command) => return Err(format!("Unknown ctl command '{}'", command).into()),
+        None => return Err(USAGE.into()),
+    };
+
+    let response = control::request(&request)?;
+    match request {
+        Request::Status => {
+            println!(
+                "QMKonnect {} (pid {}) on {}, {}",
+                response["version"].as_str().unwrap_or("?"),
+                response["pid"],
+                response["platform"].as_str().unwrap_or("?"),
+                if response["paused"] == true {
+                    "paused"
+                } else {
+                    "active"
+                }
+            );
+            let pinned = &response["override"];
+            if !pinned.is_null() {
+                println!(
+                    "Override: {} {} ({})",
+                    pinned["app_class"].as_str().unwrap_or(""),
+                    pinned["title"].as_str().unwrap_or(""),
+                    match pinned["seconds"].as_u64() {
+                        Some(seconds) => format!("{}m{}s left", seconds / 60, seconds % 60),
+                        None => "until cleared".to_string(),
+                    }
+                );
+            }
+            for pushed in response["pushed"].as_array().into_iter().flatten() {
+                let seconds = pushed["seconds"].as_u64().unwrap_or(0);
+                println!(
+                    "Pushed by {}: {} {} (priority {}, {}m{}s left)",
+                    pushed["source"].as_str().unwrap_or("?"),
+                    pushed["app_class"].as_str().unwrap_or(""),
+                    pushed["title"].as_str().unwrap_or(""),
+                    pushed["priority"],
+                    seconds / 60,
+                    seconds % 60
+                );
+            }
+        }
+        Request::Current => {
+            let window = &response["window"];
+            if window.is_null() {
+                println!("No window reported yet");
+            } else {
+                println!("Class: {}", window["app_class"].as_str().unwrap_or(""));
+                println!("Title: {}", window["title"].as_str().unwrap_or(""));
+                if let Some(fields) = window["fields"].as_object() {
+                    for (name, value) in fields {
+                        println!("{}: {}", name, value.as_str().unwrap_or(""));
+                    }
+                }
+            }
+            if let Some(message) = response["message"].as_str() {
+                println!("Message: {}", core::encoder::sanitize(message));
+            }
+        }
+        Request::Toggle => println!(
+            "{}",
+            if response["paused"] == true {
+                "Paused"
+            } else {
+                "Resumed"
+            }
+        ),
+        _ => println!("OK"),
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run_override(args: &[String]) -> Result<(), Box<dyn Error>> {
+    use control::Request;
+
+    let value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|position| args.get(position + 1))
+    };
+
+    let request = if args.iter().any(|arg| arg == "--clear") {
+        Request::ClearOverride
+    } else {
+        let usage =
+            "Usage: qmkonnect override --class <class> [--title <title>] [--for <duration>]";
+        Request::Override {
+            app_class: value("--class").ok_or(usage)?.clone(),
+            title: value("--title").cloned().unwrap_or_default(),
+            seconds: value("--for")
+                .map(|duration| control::parse_duration(duration))
+                .transpose()?
+                .map(|duration| duration.as_secs()),
+        }
+    };
+
+    control::request(&request)?;
+    println!("OK");
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run_push(args: &[String]) -> Result<(), Box<dyn Error>> {
+    use control::Request;
+
+    let value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|position| args.get(position + 1))
+    };
+    let source = value("--source")
+        .cloned()
+        .unwrap_or_else(control::default_source);
+
+    let request = if args.iter().any(|arg| arg == "--clear") {
+        Request::ClearPush { source }
+    } else {
+        let usage = "Usage: qmkonnect push --class <class> [--title <title>] [--priority <n>] [--for <duration>] [--source <name>]";
+        Request::Push {
+            source,
+            app_class: value("--class").ok_or(usage)?.clone(),
+            title: value("--title").cloned().unwrap_or_default(),
+            fields: Default::default(),
+            priority: value("--priority")
+                .map(|priority| priority.parse())
+                .transpose()
+                .map_err(|_| "The priority must be a whole number")?
+                .unwrap_or(0),
+            seconds: value("--for")
+                .map(|duration| control::parse_duration(duration))
+                .transpose()?
+                .map(|duration| duration.as_secs()),
+        }
+    };
+
+    control::request(&request)?;
+    println!("OK");
+    Ok(())
+}
+
 fn get_config_path() -> Result<PathBuf, Box<dyn Error>> {
     // Get platform-specific config paths
     let config_paths = platforms::get_config_paths();
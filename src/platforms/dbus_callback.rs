@@ -0,0 +1,76 @@
+#![cfg(all(
+    target_os = "linux",
+    any(feature = "kwin", feature = "gnome", feature = "dbus")
+))]
+// Shared plumbing for backends where a compositor-side script calls back into
+// qmkonnect over the session bus instead of being queried by us.
+#[cfg(any(feature = "kwin", feature = "gnome"))]
+use crate::core::notifier;
+#[cfg(any(feature = "kwin", feature = "gnome"))]
+use crate::core::types::WindowInfo;
+#[cfg(any(feature = "kwin", feature = "gnome"))]
+use std::sync::mpsc::Receiver;
+
+// Well-known name owned by qmkonnect while a callback-based backend runs
+#[cfg(any(feature = "kwin", feature = "gnome"))]
+pub(super) const BUS_NAME: &str = "io.github.qmkonnect";
+
+// Forward every window reported by the compositor until the sender goes away
+#[cfg(any(feature = "kwin", feature = "gnome"))]
+pub(super) fn forward_windows(receiver: Receiver<WindowInfo>, verbose: bool) {
+    for window_info in receiver {
+        if verbose {
+            println!(
+                "Window changed - Class: '{}', Title: '{}'",
+                window_info.app_class, window_info.title
+            );
+        }
+
+        if let Err(e) = notifier::notify_qmk(&window_info, verbose) {
+            eprintln!("Error notifying QMK: {}", e);
+        }
+    }
+}
+
+// A throwaway dbus-daemon so tests never touch the user's session bus
+#[cfg(test)]
+pub(crate) struct PrivateBus {
+    daemon: std::process::Child,
+    address: String,
+}
+
+#[cfg(test)]
+impl PrivateBus {
+    pub(crate) fn start() -> Option<Self> {
+        use std::io::{BufRead, BufReader};
+        use std::process::{Command, Stdio};
+
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+
+        let mut address = String::new();
+        let stdout = daemon.stdout.take()?;
+        BufReader::new(stdout).read_line(&mut address).ok()?;
+
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+
+    pub(crate) fn builder(&self) -> zbus::blocking::connection::Builder<'static> {
+        zbus::blocking::connection::Builder::address(self.address.as_str()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
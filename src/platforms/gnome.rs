@@ -0,0 +1,250 @@
+#![cfg(all(target_os = "linux", feature = "gnome"))]
+use crate::core::types::WindowInfo;
+use crate::platforms::dbus_callback::{self, BUS_NAME};
+use crate::platforms::WindowMonitor;
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc,
+};
+use zbus::blocking::{connection, Connection};
+use zbus::interface;
+
+// Object the GNOME Shell extension calls back into
+const CALLBACK_PATH: &str = "/io/github/qmkonnect/Gnome";
+
+// Companion extension shipped with qmkonnect
+const EXTENSION_UUID: &str = "qmkonnect@dabstractor.github.io";
+const EXTENSION_METADATA: &str =
+    include_str!("../../packaging/linux/gnome-extension/metadata.json");
+const EXTENSION_SCRIPT: &str = include_str!("../../packaging/linux/gnome-extension/extension.js");
+
+// D-Bus object the extension calls whenever focus or the focused title changes
+struct GnomeCallback {
+    sender: mpsc::Sender<WindowInfo>,
+}
+
+#[interface(name = "io.github.qmkonnect.Gnome")]
+impl GnomeCallback {
+    fn focus_changed(&self, wm_class: String, title: String) {
+        // The receiver only goes away when the monitor is shutting down
+        let _ = self.sender.send(WindowInfo::new(wm_class, title));
+    }
+}
+
+pub struct GnomeMonitor {
+    connection: Option<Connection>,
+    verbose: bool,
+}
+
+impl GnomeMonitor {
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            connection: None,
+            verbose,
+        }
+    }
+}
+
+impl WindowMonitor for GnomeMonitor {
+    fn platform_name(&self) -> &str {
+        "GNOME Shell"
+    }
+
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.verbose {
+            println!("Starting GNOME Shell window monitor");
+        }
+
+        match extension_dir() {
+            Some(dir) if dir.join("extension.js").exists() => {}
+            _ => eprintln!(
+                "GNOME Shell extension {} is not installed, run 'qmkonnect install-gnome-extension'",
+                EXTENSION_UUID
+            ),
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let connection = serve_callback(connection::Builder::session()?, sender)
+            .map_err(|e| format!("Failed to register {} on the session bus: {}", BUS_NAME, e))?;
+
+        self.connection = Some(connection);
+
+        // Blocks for as long as the connection (and with it the sender) is alive
+        dbus_callback::forward_windows(receiver, self.verbose);
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.connection = None;
+        Ok(())
+    }
+}
+
+// Detect a GNOME Shell session, where only the extension can see focus changes
+pub fn is_gnome_session() -> bool {
+    env::var("XDG_CURRENT_DESKTOP")
+        .map(|desktop| desktop.split(':').any(|d| d.eq_ignore_ascii_case("GNOME")))
+        .unwrap_or(false)
+}
+
+// Install the companion extension for the current user and try to enable it
+pub fn install_gnome_extension(verbose: bool) -> Result<(), Box<dyn Error>> {
+    let dir = extension_dir().ok_or("Could not determine GNOME Shell extensions directory")?;
+    write_extension(&dir)?;
+
+    println!("GNOME Shell extension installed to: {}", dir.display());
+
+    let enabled = Command::new("gnome-extensions")
+        .args(["enable", EXTENSION_UUID])
+        .output();
+    match enabled {
+        Ok(output) if output.status.success() => {
+            println!("Extension enabled.");
+        }
+        Ok(output) => {
+            if verbose {
+                println!(
+                    "gnome-extensions enable failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            println!("Note: Log out and back in, then run 'gnome-extensions enable {}'.", EXTENSION_UUID);
+        }
+        Err(e) => {
+            if verbose {
+                println!("Could not run gnome-extensions: {}", e);
+            }
+            println!("Note: Enable the extension with the Extensions app after logging back in.");
+        }
+    }
+
+    Ok(())
+}
+
+fn extension_dir() -> Option<PathBuf> {
+    let data_dir = if let Ok(xdg_data) = env::var("XDG_DATA_HOME") {
+        PathBuf::from(xdg_data)
+    } else {
+        dirs::home_dir()?.join(".local").join("share")
+    };
+
+    Some(
+        data_dir
+            .join("gnome-shell")
+            .join("extensions")
+            .join(EXTENSION_UUID),
+    )
+}
+
+fn write_extension(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("metadata.json"), EXTENSION_METADATA)?;
+    fs::write(dir.join("extension.js"), EXTENSION_SCRIPT)?;
+    Ok(())
+}
+
+fn serve_callback(
+    builder: connection::Builder,
+    sender: mpsc::Sender<WindowInfo>,
+) -> zbus::Result<Connection> {
+    builder
+        .name(BUS_NAME)?
+        .serve_at(CALLBACK_PATH, GnomeCallback { sender })?
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platforms::dbus_callback::PrivateBus;
+    use std::time::Duration;
+
+    // Bus name, path, interface and method of the extension's D-Bus call,
+    // with the constants it names looked up
+    fn extension_call() -> Vec<String> {
+        let (_, call) = EXTENSION_SCRIPT.split_once("Gio.DBus.session.call(").unwrap();
+        call.split(',')
+            .take(4)
+            .map(|argument| {
+                let argument = argument.trim();
+                let value = argument.strip_prefix('\'').unwrap_or_else(|| {
+                    let constant = format!("const {} = '", argument);
+                    EXTENSION_SCRIPT.split_once(&constant).unwrap().1
+                });
+                value.split('\'').next().unwrap().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_extension_metadata() {
+        let metadata: serde_json::Value = serde_json::from_str(EXTENSION_METADATA).unwrap();
+        assert_eq!(metadata["uuid"], EXTENSION_UUID);
+    }
+
+    #[test]
+    fn test_write_extension() {
+        let dir = env::temp_dir()
+            .join(format!("qmkonnect-gnome-{}", std::process::id()))
+            .join(EXTENSION_UUID);
+
+        write_extension(&dir).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("metadata.json")).unwrap(),
+            EXTENSION_METADATA
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("extension.js")).unwrap(),
+            EXTENSION_SCRIPT
+        );
+
+        let _ = fs::remove_dir_all(dir.parent().unwrap());
+    }
+
+    #[test]
+    fn test_focus_changed_reaches_monitor() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let _connection = serve_callback(bus.builder(), sender).unwrap();
+
+        // Emulate the extension's Gio.DBus.session.call(), addressed as extension.js does
+        let call = extension_call();
+        assert_eq!(call, [BUS_NAME, CALLBACK_PATH, "io.github.qmkonnect.Gnome", "FocusChanged"]);
+        let shell = bus.builder().build().unwrap();
+        let focus_changed = |wm_class: &str, title: &str| {
+            shell
+                .call_method(
+                    Some(call[0].as_str()),
+                    call[1].as_str(),
+                    Some(call[2].as_str()),
+                    call[3].as_str(),
+                    &(wm_class, title),
+                )
+                .unwrap();
+        };
+        focus_changed("org.gnome.Nautilus", "Home");
+
+        // An unfocused desktop is reported with empty strings
+        focus_changed("", "");
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            WindowInfo::new("org.gnome.Nautilus".to_string(), "Home".to_string())
+        );
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            WindowInfo::new(String::new(), String::new())
+        );
+    }
+}
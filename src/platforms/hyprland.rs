@@ -1,21 +1,17 @@
 #![cfg(all(target_os = "linux", feature = "hyprland"))]
 use crate::core::notifier;
-use crate::core::types::WindowInfo;
+use crate::core::types::{HostEvent, WindowInfo};
+use crate::platforms::hyprland_ipc::{self, ActiveWindow, HyprlandEvent, HyprlandIpc, Workspace};
 use crate::platforms::WindowMonitor;
-use hyprland::{
-    data::Client,
-    event_listener::{EventListener, WorkspaceEventData},
-    shared::HyprData,
-    shared::HyprDataActiveOptional,
-};
 use std::{
     env,
     error::Error,
-    fs,
+    io::BufReader,
+    os::unix::net::UnixStream,
     path::PathBuf,
     sync::{Arc, Mutex},
     thread,
-    time::{Duration, SystemTime},
+    time::Duration,
 };
 
 // Custom struct to store window state that implements Clone
@@ -23,6 +19,10 @@ use std::{
 struct WindowState {
     app_class: String,
     title: String,
+    fullscreen: bool,
+    workspace: Option<Workspace>,
+    floating: bool,
+    pid: Option<u32>,
 }
 
 impl Clone for WindowState {
@@ -30,13 +30,71 @@ impl Clone for WindowState {
         Self {
             app_class: self.app_class.clone(),
             title: self.title.clone(),
+            fullscreen: self.fullscreen,
+            workspace: self.workspace.clone(),
+            floating: self.floating,
+            pid: self.pid,
+        }
+    }
+}
+
+impl WindowState {
+    // Focus is on an empty workspace
+    fn empty(workspace: Option<Workspace>) -> Self {
+        Self {
+            app_class: "".to_string(),
+            title: "".to_string(),
+            fullscreen: false,
+            workspace,
+            floating: false,
+            pid: None,
+        }
+    }
+}
+
+// The focused window as last reported to the keyboard
+#[derive(Default)]
+struct TrackedWindow {
+    address: String,
+    state: Option<WindowState>,
+}
+
+impl TrackedWindow {
+    // Record the focused window, returning it only if the keyboard hasn't seen it yet
+    fn update(&mut self, address: String, state: WindowState) -> Option<WindowInfo> {
+        self.address = address;
+        if self.state.as_ref() == Some(&state) {
+            return None;
         }
+
+        let mut window_info = WindowInfo::new(state.app_class.clone(), state.title.clone());
+        window_info.fullscreen = state.fullscreen;
+        window_info.floating = state.floating;
+        window_info.pid = state.pid;
+        if let Some(workspace) = &state.workspace {
+            window_info
+                .fields
+                .insert("workspace".to_string(), workspace.name.clone());
+            window_info
+                .fields
+                .insert("workspace_id".to_string(), workspace.id.to_string());
+        }
+        self.state = Some(state);
+        Some(window_info)
     }
 }
 
+// Something the keyboard should hear about after an event
+#[derive(PartialEq, Debug)]
+enum Update {
+    Window(WindowInfo),
+    Host(HostEvent),
+}
+
 pub struct HyprlandMonitor {
-    event_listener: Option<EventListener>,
-    last_window_state: Arc<Mutex<Option<WindowState>>>,
+    // Instance we currently follow, replaced when Hyprland restarts
+    instance: Arc<Mutex<Option<HyprlandIpc>>>,
+    tracked_window: Arc<Mutex<TrackedWindow>>,
     polling_active: Arc<Mutex<bool>>,
     verbose: bool,
 }
@@ -44,12 +102,49 @@ pub struct HyprlandMonitor {
 impl HyprlandMonitor {
     pub fn new(verbose: bool) -> Self {
         Self {
-            event_listener: None,
-            last_window_state: Arc::new(Mutex::new(None)),
+            instance: Arc::new(Mutex::new(None)),
+            tracked_window: Arc::new(Mutex::new(TrackedWindow::default())),
             polling_active: Arc::new(Mutex::new(false)),
             verbose,
         }
     }
+
+    // Opt-in fallback for setups where some focus change never produces an event
+    fn start_polling(&self, poll_interval: Duration) {
+        {
+            let mut active = self.polling_active.lock().unwrap();
+            *active = true;
+        }
+
+        let instance = Arc::clone(&self.instance);
+        let polling_active = Arc::clone(&self.polling_active);
+        let tracked_window = Arc::clone(&self.tracked_window);
+        let verbose = self.verbose;
+
+        thread::spawn(move || {
+            while *polling_active.lock().unwrap() {
+                let Some(ipc) = instance.lock().unwrap().clone() else {
+                    thread::sleep(poll_interval);
+                    continue;
+                };
+
+                match query_active_window(&ipc, &tracked_window) {
+                    Ok(Some(window_info)) => {
+                        if verbose {
+                            println!("Poll detected window state change");
+                        }
+                        notify(&window_info, verbose);
+                    }
+                    Ok(None) => {}
+                    Err(err) => eprintln!("Error polling window state: {}", err),
+                }
+                thread::sleep(poll_interval);
+            }
+            if verbose {
+                println!("Window state polling thread stopped");
+            }
+        });
+    }
 }
 
 impl WindowMonitor for HyprlandMonitor {
@@ -58,341 +153,343 @@ impl WindowMonitor for HyprlandMonitor {
     }
 
     fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        let config = crate::core::runtime_config().hyprland;
+
         // Check if we're running in Hyprland
-        if !is_hyprland_running() {
-            return Err("Not running in Hyprland environment".into());
-        }
+        let mut ipc = find_instance(config.instance.as_deref())
+            .map_err(|e| format!("Not running in Hyprland environment: {}", e))?;
 
         if self.verbose {
             println!("Starting Hyprland window monitor");
+            println!("Following Hyprland instance {}", ipc.signature());
         }
 
-        {
-            // Verify we can connect to Hyprland initially
-            if let Err(e) = hyprland::data::Monitors::get() {
-                return Err(format!("Failed to connect to Hyprland: {}", e).into());
-            }
-
-            // Set polling to active
-            {
-                let mut active = self.polling_active.lock().unwrap();
-                *active = true;
+        // Verify we can connect to Hyprland initially
+        let mut events = ipc.events().map_err(|e| {
+            format!(
+                "Failed to connect to Hyprland event socket: {}\nAre you sure Hyprland is running?",
+                e
+            )
+        })?;
+        *self.instance.lock().unwrap() = Some(ipc.clone());
+
+        // Events only describe changes, so report whatever has focus right now
+        self.refresh(&ipc);
+
+        if let Some(poll_interval_ms) = config.poll_interval_ms {
+            if self.verbose {
+                println!("Polling Hyprland every {}ms", poll_interval_ms);
             }
+            self.start_polling(Duration::from_millis(poll_interval_ms));
+        }
 
-            // Start polling thread for scratchpad detection
-            let polling_active = Arc::clone(&self.polling_active);
-            let last_window_state = Arc::clone(&self.last_window_state);
-            let verbose = self.verbose;
-
-            thread::spawn(move || {
-                let poll_interval = Duration::from_millis(100);
-                while *polling_active.lock().unwrap() {
-                    // Poll for window state
-                    if let Err(err) = poll_window_state(&last_window_state, verbose) {
-                        eprintln!("Error polling window state: {}", err);
+        loop {
+            match hyprland_ipc::next_event(&mut events) {
+                Ok(event) => match handle_event(&event, &ipc, &self.tracked_window, self.verbose) {
+                    Ok(Some(update)) => report(&update, self.verbose),
+                    Ok(None) => {}
+                    Err(err) => eprintln!("Error handling {:?}: {}", event, err),
+                },
+                Err(e) => {
+                    if self.verbose {
+                        println!("Lost connection to Hyprland: {}", e);
                     }
-                    thread::sleep(poll_interval);
+                    (ipc, events) = self.rebind(config.instance.as_deref());
                 }
-                if verbose {
-                    println!("Window state polling thread stopped");
-                }
-            });
-
-            let start: SystemTime = SystemTime::now();
-            let mut delay_ms = 101;
-
-            loop {
-                // Create a new event listener for each attempt
-                let mut listener = EventListener::new();
-                let verbose = self.verbose;
-                let last_window_state = Arc::clone(&self.last_window_state);
-
-                // Set up the window change handler
-                let lwc = Arc::clone(&last_window_state);
-                listener.add_active_window_changed_handler(move |_| {
-                    if let Err(err) = handle_window_state_change(&lwc, verbose) {
-                        eprintln!("Error handling window change: {}", err);
-                    }
-                });
-
-                // Add workspace change handler
-                let lws = Arc::clone(&last_window_state);
-                listener.add_workspace_changed_handler(move |workspace_event| {
-                    if let Err(err) = handle_workspace_change(workspace_event, &lws, verbose) {
-                        eprintln!("Error handling workspace change: {}", err);
-                    }
-                });
+            }
+        }
+    }
 
-                // Add window closed handler
-                let lwc = Arc::clone(&last_window_state);
-                listener.add_window_closed_handler(move |_| {
-                    if let Err(err) = handle_window_state_change(&lwc, verbose) {
-                        eprintln!("Error handling window close: {}", err);
-                    }
-                });
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        // Stop the polling thread
+        let mut active = self.polling_active.lock().unwrap();
+        *active = false;
+        Ok(())
+    }
+}
 
-                // Add layer surface (like scratchpads) handlers - note the correct method names
-                let lws = Arc::clone(&last_window_state);
-                listener.add_layer_opened_handler(move |_| {
-                    if let Err(err) = handle_window_state_change(&lws, verbose) {
-                        eprintln!("Error handling layer open: {}", err);
+impl HyprlandMonitor {
+    // Hyprland quit, crashed or restarted under a new instance signature. Wait
+    // for an instance to come up and follow it from then on.
+    fn rebind(&self, configured: Option<&str>) -> (HyprlandIpc, BufReader<UnixStream>) {
+        let previous = self
+            .instance
+            .lock()
+            .unwrap()
+            .take()
+            .map(|ipc| ipc.signature())
+            .unwrap_or_default();
+        let mut delay_ms = 101;
+
+        loop {
+            // Sleep with exponential backoff
+            thread::sleep(Duration::from_millis(delay_ms));
+
+            let connected = find_instance(configured).and_then(|ipc| Ok((ipc.events()?, ipc)));
+            match connected {
+                Ok((events, ipc)) => {
+                    if self.verbose {
+                        if ipc.signature() != previous {
+                            println!("Hyprland restarted, following instance {}", ipc.signature());
+                        } else {
+                            println!("Reconnected to Hyprland instance {}", ipc.signature());
+                        }
                     }
-                });
 
-                let lws = Arc::clone(&last_window_state);
-                listener.add_layer_closed_handler(move |_| {
-                    if let Err(err) = handle_window_state_change(&lws, verbose) {
-                        eprintln!("Error handling layer close: {}", err);
-                    }
-                });
-
-                // Try to start the listener
-                match listener.start_listener() {
-                    Ok(_) => {
-                        // This branch never executes because start_listener blocks
-                        // until an error occurs. Including it for API correctness.
-                        self.event_listener = Some(listener);
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        if start.elapsed().unwrap().as_millis() < 2001 {
-                            // Stop polling thread
-                            {
-                                let mut active = self.polling_active.lock().unwrap();
-                                *active = false;
-                            }
-                            return Err(format!("Failed to start event listener: {}\nAre you sure Hyprland is running?", e).into());
-                        }
-                        // Otherwise, retry with exponential backoff
-                        if self.verbose {
-                            println!(
-                                "Lost connection to Hyprland, retrying in {}ms: {}",
-                                delay_ms, e
-                            );
-                        }
-                        // Sleep with exponential backoff
-                        std::thread::sleep(std::time::Duration::from_millis(delay_ms));
-                        // Exponential backoff with a cap
-                        delay_ms = std::cmp::min(delay_ms * 3, 10000); // Cap at 10 seconds
+                    // Nothing from the old instance carries over, including its submap
+                    *self.tracked_window.lock().unwrap() = TrackedWindow::default();
+                    report(
+                        &Update::Host(HostEvent::Submap(String::new())),
+                        self.verbose,
+                    );
+                    *self.instance.lock().unwrap() = Some(ipc.clone());
+                    self.refresh(&ipc);
+                    return (ipc, events);
+                }
+                Err(e) => {
+                    if self.verbose {
+                        println!("Waiting for Hyprland, retrying in {}ms: {}", delay_ms, e);
                     }
+                    // Exponential backoff with a cap
+                    delay_ms = std::cmp::min(delay_ms * 3, 10000); // Cap at 10 seconds
                 }
             }
         }
     }
 
-    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
-        {
-            // Stop the polling thread
-            {
-                let mut active = self.polling_active.lock().unwrap();
-                *active = false;
-            }
-            self.event_listener = None;
+    fn refresh(&self, ipc: &HyprlandIpc) {
+        match query_active_window(ipc, &self.tracked_window) {
+            Ok(Some(window_info)) => notify(&window_info, self.verbose),
+            Ok(None) => {}
+            Err(err) => eprintln!("Failed to get active window info: {}", err),
         }
-        Ok(())
-    }
-}
 
-pub(crate) fn is_hyprland_running() -> bool {
-    match check_hyprland_environment() {
-        Ok(_) => true,
-        Err(e) => {
-            eprintln!("Hyprland environment check failed: {}", e);
-            false
+        match ipc.active_layout() {
+            Ok(Some(layout)) => report(&Update::Host(HostEvent::Layout(layout)), self.verbose),
+            Ok(None) => {}
+            Err(err) => eprintln!("Failed to get keyboard layout: {}", err),
         }
     }
 }
 
-fn check_hyprland_environment() -> Result<(), Box<dyn Error>> {
-    // First try environment variable approach
-    if let Ok(signature) = env::var("HYPRLAND_INSTANCE_SIGNATURE") {
-        if let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") {
-            let socket_path = PathBuf::from(&runtime_dir)
-                .join("hypr")
-                .join(signature)
-                .join(".socket.sock");
-
-            if socket_path.exists() {
-                return Ok(());
-            }
+// Locate the instance to follow. Unlike HYPRLAND_INSTANCE_SIGNATURE alone this
+// copes with several instances and with the one we started under being gone.
+fn find_instance(configured: Option<&str>) -> Result<HyprlandIpc, Box<dyn Error>> {
+    let runtime_dir = env::var("XDG_RUNTIME_DIR").map_err(|_| "XDG_RUNTIME_DIR is not set")?;
+    let hypr_dir = PathBuf::from(runtime_dir).join("hypr");
+    let inherited = env::var("HYPRLAND_INSTANCE_SIGNATURE").ok();
+
+    hyprland_ipc::select_instance(&hypr_dir, configured, inherited.as_deref()).map_err(|e| {
+        // If we get here, we need to check if Hyprland is actually running
+        let processes = std::process::Command::new("ps")
+            .args(["-e", "-o", "comm="])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+            .unwrap_or_default();
+
+        if processes.lines().any(|line| line.contains("Hyprland")) {
+            // Hyprland is running but we can't find the socket
+            format!("Hyprland is running but its socket was not found: {}", e).into()
+        } else {
+            "Hyprland is not running".into()
         }
+    })
+}
+
+fn notify(window_info: &WindowInfo, verbose: bool) {
+    if let Err(e) = notifier::notify_qmk(window_info, verbose) {
+        eprintln!("Error notifying QMK: {}", e);
     }
+}
 
-    // If environment variables aren't set, try to find the socket directly
-    if let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") {
-        let hypr_dir = PathBuf::from(&runtime_dir).join("hypr");
-        if hypr_dir.exists() {
-            // Look for any instance directories
-            if let Ok(entries) = fs::read_dir(&hypr_dir) {
-                for entry in entries.flatten() {
-                    let socket_path = entry.path().join(".socket.sock");
-                    if socket_path.exists() {
-                        // Found a socket! Set the environment variable
-                        let instance_sig = entry.file_name();
-                        env::set_var(
-                            "HYPRLAND_INSTANCE_SIGNATURE",
-                            instance_sig.to_string_lossy().as_ref(),
-                        );
-                        return Ok(());
-                    }
-                }
+fn report(update: &Update, verbose: bool) {
+    match update {
+        Update::Window(window_info) => notify(window_info, verbose),
+        Update::Host(event) => {
+            if let Err(e) = notifier::notify_event(event, verbose) {
+                eprintln!("Error notifying QMK: {}", e);
             }
         }
     }
-
-    // If we get here, we need to check if Hyprland is actually running
-    let ps_output = std::process::Command::new("ps")
-        .args(["-e", "-o", "comm="])
-        .output()?;
-
-    let processes = String::from_utf8_lossy(&ps_output.stdout);
-    if processes.lines().any(|line| line.contains("Hyprland")) {
-        // Hyprland is running but we can't find the socket
-        Err("Hyprland is running but socket not found. Ensure XDG_RUNTIME_DIR is set and accessible.".into())
-    } else {
-        Err("Hyprland is not running".into())
-    }
 }
 
-fn poll_window_state(
-    last_window_state: &Arc<Mutex<Option<WindowState>>>,
+// Work out what an event means for the keyboard. Returns the update to report,
+// if any.
+fn handle_event(
+    event: &HyprlandEvent,
+    ipc: &HyprlandIpc,
+    tracked_window: &Mutex<TrackedWindow>,
     verbose: bool,
-) -> Result<(), Box<dyn Error>> {
-    // Get current window state
-    let current_window_state = match Client::get_active() {
-        Ok(Some(active_window)) => Some(WindowState {
-            app_class: active_window.initial_class.clone(),
-            title: active_window.title.clone(),
-        }),
-        Ok(None) => {
+) -> Result<Option<Update>, Box<dyn Error>> {
+    let window_info = match event {
+        HyprlandEvent::ActiveWindow { address } if address.is_empty() => {
             // No active window - we're on an empty workspace
             if verbose {
-                println!("Poll detected empty workspace");
+                println!("Empty workspace detected");
             }
-            Some(WindowState {
-                app_class: "".to_string(),
-                title: "".to_string(),
-            })
+            let workspace = ipc.active_workspace()?;
+            let mut tracked = tracked_window.lock().unwrap();
+            tracked.update(String::new(), WindowState::empty(workspace))
         }
-        Err(err) => {
-            eprintln!("Failed to get active window info in poll: {}", err);
-            None
+        HyprlandEvent::WindowTitle { address, title } => {
+            let mut tracked = tracked_window.lock().unwrap();
+            // Background windows retitle themselves all the time
+            if *address != tracked.address {
+                return Ok(None);
+            }
+            let Some(state) = tracked.state.clone() else {
+                return Ok(None);
+            };
+            tracked.update(
+                address.clone(),
+                WindowState {
+                    title: title.clone(),
+                    ..state
+                },
+            )
         }
-    };
-
-    // Compare with last known state
-    let mut last_state = last_window_state.lock().unwrap();
-    let window_changed = match (&*last_state, &current_window_state) {
-        (None, Some(_)) => true,
-        (Some(_), None) => true,
-        (Some(last), Some(current)) => {
-            // Only consider it changed if either:
-            // 1. We're moving from a window to empty workspace
-            // 2. We're moving from empty workspace to a window
-            // 3. We're changing between different windows
-            // But NOT if we're staying on an empty workspace
-            if last.app_class == "empty" && current.app_class == "empty" {
-                false // Don't report repeated empty workspace states
-            } else {
-                last.app_class != current.app_class || last.title != current.title
+        HyprlandEvent::CloseWindow { address } | HyprlandEvent::MoveWindow { address, .. } => {
+            // Only relevant when it happens to the focused window
+            if *address != tracked_window.lock().unwrap().address {
+                return Ok(None);
             }
+            query_active_window(ipc, tracked_window)?
         }
-        (None, None) => false,
-    };
-
-    // If window changed, update state and notify
-    if window_changed {
-        if verbose {
-            println!("Poll detected window state change");
+        HyprlandEvent::Workspace { id, name } => {
+            handle_workspace_change(id, name, ipc, tracked_window, verbose)?
         }
-        if let Some(window_state) = &current_window_state {
-            let window_info =
-                WindowInfo::new(window_state.app_class.clone(), window_state.title.clone());
-            if let Err(e) = notifier::notify_qmk(&window_info, verbose) {
-                eprintln!("Error notifying QMK: {}", e);
+        HyprlandEvent::ActiveSpecial { workspace, .. } => {
+            // Scratchpads are special workspaces toggled on top of the current one
+            if verbose {
+                if workspace.is_empty() {
+                    println!("Special workspace hidden");
+                } else {
+                    println!("Special workspace {} shown", workspace);
+                }
             }
+            query_active_window(ipc, tracked_window)?
         }
-        *last_state = current_window_state;
-    }
-
-    Ok(())
-}
-
-fn handle_window_state_change(
-    last_window_state: &Arc<Mutex<Option<WindowState>>>,
-    verbose: bool,
-) -> Result<(), Box<dyn Error>> {
-    match Client::get_active() {
-        Ok(Some(active_window)) => {
-            let window_info = WindowInfo::new(
-                active_window.initial_class.clone(),
-                active_window.title.clone(),
-            );
-
-            // Update last known state
-            {
-                let mut last_state = last_window_state.lock().unwrap();
-                *last_state = Some(WindowState {
-                    app_class: active_window.initial_class.clone(),
-                    title: active_window.title.clone(),
-                });
-            }
-
-            if let Err(e) = notifier::notify_qmk(&window_info, verbose) {
-                eprintln!("Error notifying QMK: {}", e);
+        HyprlandEvent::Fullscreen { enabled } => {
+            if verbose {
+                println!("Fullscreen {}", if *enabled { "entered" } else { "left" });
             }
+            query_active_window(ipc, tracked_window)?
         }
-        Ok(None) => {
-            // No active window - we're on an empty workspace
+        HyprlandEvent::ActiveWindow { .. } | HyprlandEvent::FocusedMonitor { .. } => {
+            query_active_window(ipc, tracked_window)?
+        }
+        HyprlandEvent::Submap { name } => {
             if verbose {
-                println!("Empty workspace detected");
-            }
-
-            // Create a special window info for empty workspace
-            let window_info = WindowInfo::new("".to_string(), "".to_string());
-
-            // Update last known state
-            {
-                let mut last_state = last_window_state.lock().unwrap();
-                *last_state = Some(WindowState {
-                    app_class: "".to_string(),
-                    title: "".to_string(),
-                });
+                if name.is_empty() {
+                    println!("Submap reset");
+                } else {
+                    println!("Submap {} entered", name);
+                }
             }
-
-            if let Err(e) = notifier::notify_qmk(&window_info, verbose) {
-                eprintln!("Error notifying QMK: {}", e);
+            return Ok(Some(Update::Host(HostEvent::Submap(name.clone()))));
+        }
+        HyprlandEvent::ActiveLayout { keyboard, layout } => {
+            if verbose {
+                println!("Layout of {} switched to {}", keyboard, layout);
             }
+            return Ok(Some(Update::Host(HostEvent::Layout(layout.clone()))));
         }
-        Err(err) => {
-            eprintln!("Failed to get active window info: {}", err);
+        HyprlandEvent::Urgent { address } => {
+            // The window may already be gone
+            let Some(app_class) = ipc.client_class(address)? else {
+                return Ok(None);
+            };
+            if verbose {
+                println!("{} wants attention", app_class);
+            }
+            return Ok(Some(Update::Host(HostEvent::Attention(app_class))));
         }
-    }
-    Ok(())
+    };
+
+    Ok(window_info.map(Update::Window))
 }
 
 fn handle_workspace_change(
-    workspace_event: WorkspaceEventData,
-    last_window_state: &Arc<Mutex<Option<WindowState>>>,
+    id: &str,
+    name: &str,
+    ipc: &HyprlandIpc,
+    tracked_window: &Mutex<TrackedWindow>,
     verbose: bool,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<Option<WindowInfo>, Box<dyn Error>> {
     if verbose {
-        println!("Workspace changed to {}", workspace_event.id);
+        println!("Workspace changed to {} ({})", id, name);
     }
 
-    // Check if the workspace is empty by checking for active window
-    handle_window_state_change(last_window_state, verbose)
+    // Check if the workspace is empty by checking for active window. If it
+    // is, the event already told us which workspace that is.
+    let workspace = Workspace {
+        id: id.parse().unwrap_or_default(),
+        name: name.to_string(),
+    };
+    Ok(track_active_window(
+        ipc.active_window()?,
+        Some(workspace),
+        tracked_window,
+    ))
+}
+
+fn query_active_window(
+    ipc: &HyprlandIpc,
+    tracked_window: &Mutex<TrackedWindow>,
+) -> Result<Option<WindowInfo>, Box<dyn Error>> {
+    let active_window = ipc.active_window()?;
+    // Windows carry their workspace, empty workspaces have to be asked for
+    let empty_workspace = match active_window {
+        Some(_) => None,
+        None => ipc.active_workspace()?,
+    };
+
+    Ok(track_active_window(
+        active_window,
+        empty_workspace,
+        tracked_window,
+    ))
+}
+
+fn track_active_window(
+    active_window: Option<ActiveWindow>,
+    empty_workspace: Option<Workspace>,
+    tracked_window: &Mutex<TrackedWindow>,
+) -> Option<WindowInfo> {
+    let (address, state) = match active_window {
+        Some(active_window) => (
+            hyprland_ipc::normalize_address(&active_window.address),
+            WindowState {
+                app_class: active_window.initial_class,
+                title: active_window.title,
+                fullscreen: active_window.fullscreen,
+                workspace: Some(active_window.workspace)
+                    .filter(|workspace| !workspace.name.is_empty()),
+                floating: active_window.floating,
+                pid: active_window.pid,
+            },
+        ),
+        None => (String::new(), WindowState::empty(empty_workspace)),
+    };
+
+    let mut tracked = tracked_window.lock().unwrap();
+    tracked.update(address, state)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platforms::hyprland_ipc::FakeHyprland;
 
     #[test]
     fn test_window_state() {
         let state1 = WindowState {
             app_class: "App1".to_string(),
             title: "Title1".to_string(),
+            fullscreen: false,
+            workspace: None,
+            floating: false,
+            pid: None,
         };
 
         let state2 = state1.clone();
@@ -413,7 +510,228 @@ mod tests {
         assert!(!monitor.verbose);
     }
 
-    // Note: Most functionality in HyprlandMonitor heavily depends on
-    // the actual Hyprland environment, so we can only unit test the
-    // basic parts without specialized mocks.
+    fn kitty_reply(title: &str) -> String {
+        format!(
+            r#"{{"address": "0xabc", "initialClass": "kitty", "title": "{}"}}"#,
+            title
+        )
+    }
+
+    #[test]
+    fn test_focus_change_queries_active_window() {
+        let hyprland = FakeHyprland::start("focus");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(&kitty_reply("~"));
+
+        let event = HyprlandEvent::ActiveWindow {
+            address: "abc".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Window(WindowInfo::new(
+                "kitty".to_string(),
+                "~".to_string()
+            )))
+        );
+
+        // Hyprland follows activewindowv2 with workspace/monitor events that
+        // resolve to the same window, which must not be resent
+        let event = HyprlandEvent::FocusedMonitor {
+            monitor: "DP-1".to_string(),
+            workspace: "1".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_title_changes_of_focused_window_only() {
+        let hyprland = FakeHyprland::start("title");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(&kitty_reply("~"));
+        query_active_window(&ipc, &tracked_window).unwrap();
+
+        let event = HyprlandEvent::WindowTitle {
+            address: "def".to_string(),
+            title: "Spotify".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            None
+        );
+
+        let event = HyprlandEvent::WindowTitle {
+            address: "abc".to_string(),
+            title: "nvim".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Window(WindowInfo::new(
+                "kitty".to_string(),
+                "nvim".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_empty_workspace_and_scratchpad() {
+        let hyprland = FakeHyprland::start("special");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(&kitty_reply("~"));
+        query_active_window(&ipc, &tracked_window).unwrap();
+
+        let event = HyprlandEvent::ActiveWindow {
+            address: String::new(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Window(WindowInfo::new(
+                "".to_string(),
+                "".to_string()
+            )))
+        );
+
+        // Showing the scratchpad focuses its window without a workspace change
+        hyprland.set_reply(
+            r#"{"address": "0x123", "initialClass": "scratchterm", "title": "scratch"}"#,
+        );
+        let event = HyprlandEvent::ActiveSpecial {
+            workspace: "special:scratchpad".to_string(),
+            monitor: "DP-1".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Window(WindowInfo::new(
+                "scratchterm".to_string(),
+                "scratch".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_closing_background_window_is_ignored() {
+        let hyprland = FakeHyprland::start("close");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(&kitty_reply("~"));
+        query_active_window(&ipc, &tracked_window).unwrap();
+
+        // If this triggered a query the empty reply would be reported
+        hyprland.set_reply("{}");
+        let event = HyprlandEvent::CloseWindow {
+            address: "def".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_submap_is_reported_without_querying() {
+        let hyprland = FakeHyprland::start("submap");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(&kitty_reply("~"));
+        query_active_window(&ipc, &tracked_window).unwrap();
+
+        // A query would report the empty workspace
+        hyprland.set_reply("{}");
+        let event = HyprlandEvent::Submap {
+            name: "resize".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Host(HostEvent::Submap("resize".to_string())))
+        );
+        assert_eq!(tracked_window.lock().unwrap().address, "abc");
+    }
+
+    #[test]
+    fn test_urgent_window_reports_its_class() {
+        let hyprland = FakeHyprland::start("urgent");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(r#"[{"address": "0xdef", "initialClass": "Slack", "title": "Slack"}]"#);
+
+        let event = HyprlandEvent::Urgent {
+            address: "def".to_string(),
+        };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Host(HostEvent::Attention("Slack".to_string())))
+        );
+
+        hyprland.set_reply("[]");
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_fullscreen_transition_is_reported() {
+        let hyprland = FakeHyprland::start("fullscreen");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(&kitty_reply("~"));
+        query_active_window(&ipc, &tracked_window).unwrap();
+
+        hyprland.set_reply(
+            r#"{"address": "0xabc", "initialClass": "kitty", "title": "~", "fullscreen": 1}"#,
+        );
+        let mut expected = WindowInfo::new("kitty".to_string(), "~".to_string());
+        expected.fullscreen = true;
+        let event = HyprlandEvent::Fullscreen { enabled: true };
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Window(expected.clone()))
+        );
+
+        // Title changes keep the fullscreen state
+        let event = HyprlandEvent::WindowTitle {
+            address: "abc".to_string(),
+            title: "htop".to_string(),
+        };
+        expected.title = "htop".to_string();
+        assert_eq!(
+            handle_event(&event, &ipc, &tracked_window, false).unwrap(),
+            Some(Update::Window(expected))
+        );
+    }
+
+    #[test]
+    fn test_workspace_and_process_are_reported_with_the_window() {
+        let hyprland = FakeHyprland::start("workspaces");
+        let ipc = hyprland.ipc();
+        let tracked_window = Mutex::new(TrackedWindow::default());
+        hyprland.set_reply(
+            r#"{"address": "0xabc", "initialClass": "kitty", "title": "~", "workspace": {"id": -98, "name": "special:scratchpad"}, "floating": true, "pid": 4242}"#,
+        );
+        let window_info = query_active_window(&ipc, &tracked_window).unwrap().unwrap();
+        assert!(window_info.floating);
+        assert_eq!(window_info.pid, Some(4242));
+        assert_eq!(window_info.fields["workspace"], "special:scratchpad");
+        assert_eq!(window_info.fields["workspace_id"], "-98");
+
+        // Switching to an empty workspace reports the workspace from the event
+        hyprland.set_reply("{}");
+        let event = HyprlandEvent::Workspace {
+            id: "9".to_string(),
+            name: "games".to_string(),
+        };
+        let Some(Update::Window(window_info)) =
+            handle_event(&event, &ipc, &tracked_window, false).unwrap()
+        else {
+            panic!("empty workspace was not reported");
+        };
+        assert_eq!(window_info.app_class, "");
+        assert_eq!(window_info.fields["workspace"], "games");
+        assert_eq!(window_info.fields["workspace_id"], "9");
+    }
 }
@@ -0,0 +1,706 @@
+#![cfg(all(target_os = "linux", feature = "hyprland"))]
+// Minimal client for Hyprland's IPC: requests go to .socket.sock, events are
+// streamed line by line from .socket2.sock as `EVENT>>DATA`.
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[derive(Clone)]
+pub struct HyprlandIpc {
+    instance_dir: PathBuf,
+}
+
+// Subset of `j/activewindow` we care about
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ActiveWindow {
+    pub address: String,
+    #[serde(rename = "initialClass")]
+    pub initial_class: String,
+    pub title: String,
+    // A bool before Hyprland 0.42, a fullscreen mode (0 for none) since
+    #[serde(default, deserialize_with = "deserialize_fullscreen")]
+    pub fullscreen: bool,
+    #[serde(default)]
+    pub workspace: Workspace,
+    #[serde(default)]
+    pub floating: bool,
+    // -1 for windows without a process of their own, e.g. some XWayland clients
+    #[serde(default, deserialize_with = "deserialize_pid")]
+    pub pid: Option<u32>,
+}
+
+// As found in `j/activeworkspace` and in each window. Special workspaces have
+// negative ids and names like "special:scratchpad".
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Workspace {
+    pub id: i64,
+    pub name: String,
+}
+
+fn deserialize_fullscreen<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(fullscreen) => Ok(fullscreen),
+        serde_json::Value::Number(mode) => Ok(mode.as_u64() != Some(0)),
+        _ => Ok(false),
+    }
+}
+
+fn deserialize_pid<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pid = Option::<i64>::deserialize(deserializer)?;
+    Ok(pid
+        .and_then(|pid| u32::try_from(pid).ok())
+        .filter(|pid| *pid > 0))
+}
+
+// Subset of each entry in `j/clients`
+#[derive(Deserialize)]
+struct Client {
+    address: String,
+    #[serde(rename = "initialClass")]
+    initial_class: String,
+}
+
+// Subset of `j/devices` we care about
+#[derive(Deserialize)]
+struct Devices {
+    keyboards: Vec<Keyboard>,
+}
+
+#[derive(Deserialize)]
+struct Keyboard {
+    active_keymap: String,
+    // Only reported by newer releases
+    #[serde(default)]
+    main: bool,
+}
+
+// Events the monitor reacts to, everything else is ignored
+#[derive(Debug, PartialEq)]
+pub enum HyprlandEvent {
+    // activewindowv2>>ADDRESS, empty when nothing is focused
+    ActiveWindow { address: String },
+    // windowtitlev2>>ADDRESS,TITLE
+    WindowTitle { address: String, title: String },
+    // workspacev2>>ID,NAME
+    Workspace { id: String, name: String },
+    // closewindow>>ADDRESS
+    CloseWindow { address: String },
+    // focusedmon>>MONITOR,WORKSPACE
+    FocusedMonitor { monitor: String, workspace: String },
+    // movewindowv2>>ADDRESS,WORKSPACEID,WORKSPACENAME
+    MoveWindow { address: String, workspace: String },
+    // activespecial>>WORKSPACE,MONITOR, empty workspace when the special workspace is hidden
+    ActiveSpecial { workspace: String, monitor: String },
+    // submap>>NAME, empty when returning to the global map
+    Submap { name: String },
+    // activelayout>>KEYBOARD,LAYOUT
+    ActiveLayout { keyboard: String, layout: String },
+    // urgent>>ADDRESS
+    Urgent { address: String },
+    // fullscreen>>0|1, for the focused window
+    Fullscreen { enabled: bool },
+}
+
+impl HyprlandIpc {
+    pub fn new(instance_dir: PathBuf) -> Self {
+        Self { instance_dir }
+    }
+
+    // HYPRLAND_INSTANCE_SIGNATURE of the instance we talk to
+    pub fn signature(&self) -> String {
+        self.instance_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    // A stale socket file survives a crashed compositor, so actually connect
+    pub fn is_alive(&self) -> bool {
+        UnixStream::connect(self.instance_dir.join(".socket.sock")).is_ok()
+    }
+
+    // Send a request to the command socket and return the raw reply
+    pub fn query(&self, request: &str) -> io::Result<String> {
+        let mut stream = UnixStream::connect(self.instance_dir.join(".socket.sock"))?;
+        stream.write_all(request.as_bytes())?;
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply)?;
+        Ok(reply)
+    }
+
+    pub fn active_window(&self) -> Result<Option<ActiveWindow>, Box<dyn Error>> {
+        let reply = self.query("j/activewindow")?;
+        let value: serde_json::Value = serde_json::from_str(&reply)?;
+
+        // Hyprland answers `{}` when no window has focus
+        if value.get("address").is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_value(value)?))
+    }
+
+    // Workspace focused on the focused monitor, None if Hyprland doesn't say
+    pub fn active_workspace(&self) -> Result<Option<Workspace>, Box<dyn Error>> {
+        let workspace: Workspace = serde_json::from_str(&self.query("j/activeworkspace")?)?;
+        Ok(Some(workspace).filter(|workspace| !workspace.name.is_empty()))
+    }
+
+    // Class of any mapped window, not just the focused one
+    pub fn client_class(&self, address: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let clients: Vec<Client> = serde_json::from_str(&self.query("j/clients")?)?;
+        Ok(clients
+            .into_iter()
+            .find(|client| normalize_address(&client.address) == address)
+            .map(|client| client.initial_class))
+    }
+
+    // Layout of the main keyboard, or of the first one if none is marked main
+    pub fn active_layout(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let devices: Devices = serde_json::from_str(&self.query("j/devices")?)?;
+        let keyboard = devices
+            .keyboards
+            .iter()
+            .find(|keyboard| keyboard.main)
+            .or(devices.keyboards.first());
+
+        Ok(keyboard.map(|keyboard| keyboard.active_keymap.clone()))
+    }
+
+    // Connect to the event socket
+    pub fn events(&self) -> io::Result<BufReader<UnixStream>> {
+        let stream = UnixStream::connect(self.instance_dir.join(".socket2.sock"))?;
+        Ok(BufReader::new(stream))
+    }
+}
+
+// Pick the instance to follow from $XDG_RUNTIME_DIR/hypr. A signature chosen
+// in config always wins; otherwise the one we were started under, as long as
+// it is still alive, then the most recently started live instance.
+pub fn select_instance(
+    hypr_dir: &Path,
+    configured: Option<&str>,
+    inherited: Option<&str>,
+) -> Result<HyprlandIpc, Box<dyn Error>> {
+    if let Some(signature) = configured {
+        let ipc = HyprlandIpc::new(hypr_dir.join(signature));
+        if !ipc.is_alive() {
+            return Err(
+                format!("Configured Hyprland instance {} is not running", signature).into(),
+            );
+        }
+        return Ok(ipc);
+    }
+
+    if let Some(signature) = inherited {
+        let ipc = HyprlandIpc::new(hypr_dir.join(signature));
+        if ipc.is_alive() {
+            return Ok(ipc);
+        }
+    }
+
+    live_instances(hypr_dir)
+        .into_iter()
+        .max_by_key(|(started, _)| *started)
+        .map(|(_, ipc)| ipc)
+        .ok_or_else(|| {
+            format!(
+                "No running Hyprland instance found in {}",
+                hypr_dir.display()
+            )
+            .into()
+        })
+}
+
+// Every instance directory with a command socket that accepts connections
+fn live_instances(hypr_dir: &Path) -> Vec<(SystemTime, HyprlandIpc)> {
+    let Ok(entries) = fs::read_dir(hypr_dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let started = entry
+                .path()
+                .join(".socket.sock")
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()?;
+            let ipc = HyprlandIpc::new(entry.path());
+            ipc.is_alive().then_some((started, ipc))
+        })
+        .collect()
+}
+
+// Block until the next event we care about, failing once the socket closes
+pub fn next_event(reader: &mut impl BufRead) -> io::Result<HyprlandEvent> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Hyprland closed the event socket",
+            ));
+        }
+
+        if let Some(event) = parse_event(line.trim_end_matches('\n')) {
+            return Ok(event);
+        }
+    }
+}
+
+pub fn parse_event(line: &str) -> Option<HyprlandEvent> {
+    let (name, data) = line.split_once(">>")?;
+
+    let event = match name {
+        "activewindowv2" => HyprlandEvent::ActiveWindow {
+            address: normalize_address(data.trim_matches(',')),
+        },
+        "windowtitlev2" => {
+            // Titles may contain commas, addresses never do
+            let (address, title) = data.split_once(',')?;
+            HyprlandEvent::WindowTitle {
+                address: normalize_address(address),
+                title: title.to_string(),
+            }
+        }
+        "workspacev2" => {
+            let (id, name) = data.split_once(',')?;
+            HyprlandEvent::Workspace {
+                id: id.to_string(),
+                name: name.to_string(),
+            }
+        }
+        "closewindow" => HyprlandEvent::CloseWindow {
+            address: normalize_address(data),
+        },
+        "focusedmon" => {
+            let (monitor, workspace) = data.split_once(',')?;
+            HyprlandEvent::FocusedMonitor {
+                monitor: monitor.to_string(),
+                workspace: workspace.to_string(),
+            }
+        }
+        "movewindowv2" => {
+            let mut parts = data.splitn(3, ',');
+            let address = parts.next()?;
+            let _workspace_id = parts.next()?;
+            HyprlandEvent::MoveWindow {
+                address: normalize_address(address),
+                workspace: parts.next()?.to_string(),
+            }
+        }
+        "activespecial" => {
+            let (workspace, monitor) = data.split_once(',')?;
+            HyprlandEvent::ActiveSpecial {
+                workspace: workspace.to_string(),
+                monitor: monitor.to_string(),
+            }
+        }
+        // Older releases announce the way back as "reset"
+        "submap" => HyprlandEvent::Submap {
+            name: if data == "reset" { "" } else { data }.to_string(),
+        },
+        "activelayout" => {
+            let (keyboard, layout) = data.split_once(',')?;
+            HyprlandEvent::ActiveLayout {
+                keyboard: keyboard.to_string(),
+                layout: layout.to_string(),
+            }
+        }
+        "fullscreen" => HyprlandEvent::Fullscreen {
+            enabled: data == "1",
+        },
+        "urgent" => HyprlandEvent::Urgent {
+            address: normalize_address(data),
+        },
+        _ => return None,
+    };
+
+    Some(event)
+}
+
+// Events carry bare hex addresses while JSON replies prefix them with 0x
+pub fn normalize_address(address: &str) -> String {
+    address.trim().trim_start_matches("0x").to_string()
+}
+
+// A fake Hyprland instance serving canned replies on its command socket
+#[cfg(test)]
+pub(crate) struct FakeHyprland {
+    pub dir: PathBuf,
+    pub reply: std::sync::Arc<std::sync::Mutex<String>>,
+}
+
+#[cfg(test)]
+impl FakeHyprland {
+    pub fn start(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("qmkonnect-hypr-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Self::start_in(dir)
+    }
+
+    // Serve an instance at `dir`, e.g. $XDG_RUNTIME_DIR/hypr/<signature>
+    pub fn start_in(dir: PathBuf) -> Self {
+        use std::os::unix::net::UnixListener;
+        use std::sync::{Arc, Mutex};
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let reply = Arc::new(Mutex::new("{}".to_string()));
+        let listener = UnixListener::bind(dir.join(".socket.sock")).unwrap();
+        let served = Arc::clone(&reply);
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 256];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(served.lock().unwrap().as_bytes());
+            }
+        });
+
+        Self { dir, reply }
+    }
+
+    pub fn set_reply(&self, reply: &str) {
+        *self.reply.lock().unwrap() = reply.to_string();
+    }
+
+    pub fn ipc(&self) -> HyprlandIpc {
+        HyprlandIpc::new(self.dir.clone())
+    }
+}
+
+#[cfg(test)]
+impl Drop for FakeHyprland {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window_events() {
+        assert_eq!(
+            parse_event("activewindowv2>>55d0c7a4b2f0"),
+            Some(HyprlandEvent::ActiveWindow {
+                address: "55d0c7a4b2f0".to_string()
+            })
+        );
+        assert_eq!(
+            parse_event("activewindowv2>>,"),
+            Some(HyprlandEvent::ActiveWindow {
+                address: String::new()
+            })
+        );
+        assert_eq!(
+            parse_event("windowtitlev2>>55d0c7a4b2f0,nvim main.rs, src"),
+            Some(HyprlandEvent::WindowTitle {
+                address: "55d0c7a4b2f0".to_string(),
+                title: "nvim main.rs, src".to_string()
+            })
+        );
+        assert_eq!(
+            parse_event("closewindow>>55d0c7a4b2f0"),
+            Some(HyprlandEvent::CloseWindow {
+                address: "55d0c7a4b2f0".to_string()
+            })
+        );
+        assert_eq!(
+            parse_event("fullscreen>>1"),
+            Some(HyprlandEvent::Fullscreen { enabled: true })
+        );
+        assert_eq!(
+            parse_event("urgent>>55d0c7a4b2f0"),
+            Some(HyprlandEvent::Urgent {
+                address: "55d0c7a4b2f0".to_string()
+            })
+        );
+        assert_eq!(
+            parse_event("movewindowv2>>55d0c7a4b2f0,3,code"),
+            Some(HyprlandEvent::MoveWindow {
+                address: "55d0c7a4b2f0".to_string(),
+                workspace: "code".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_workspace_events() {
+        assert_eq!(
+            parse_event("workspacev2>>9,games"),
+            Some(HyprlandEvent::Workspace {
+                id: "9".to_string(),
+                name: "games".to_string()
+            })
+        );
+        assert_eq!(
+            parse_event("focusedmon>>DP-1,2"),
+            Some(HyprlandEvent::FocusedMonitor {
+                monitor: "DP-1".to_string(),
+                workspace: "2".to_string()
+            })
+        );
+        assert_eq!(
+            parse_event("activespecial>>special:scratchpad,DP-1"),
+            Some(HyprlandEvent::ActiveSpecial {
+                workspace: "special:scratchpad".to_string(),
+                monitor: "DP-1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_submap_events() {
+        assert_eq!(
+            parse_event("submap>>resize"),
+            Some(HyprlandEvent::Submap {
+                name: "resize".to_string()
+            })
+        );
+        assert_eq!(
+            parse_event("submap>>"),
+            Some(HyprlandEvent::Submap {
+                name: String::new()
+            })
+        );
+        assert_eq!(
+            parse_event("submap>>reset"),
+            Some(HyprlandEvent::Submap {
+                name: String::new()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_layout_event() {
+        assert_eq!(
+            parse_event("activelayout>>at-translated-set-2-keyboard,German"),
+            Some(HyprlandEvent::ActiveLayout {
+                keyboard: "at-translated-set-2-keyboard".to_string(),
+                layout: "German".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_ignore_unknown_events() {
+        assert_eq!(parse_event("activewindow>>kitty,~"), None);
+        assert_eq!(parse_event("openlayer>>rofi"), None);
+        assert_eq!(parse_event("garbage"), None);
+    }
+
+    #[test]
+    fn test_next_event_skips_ignored_lines() {
+        let mut stream = io::Cursor::new("activewindow>>kitty,~\nactivewindowv2>>abc\n");
+        assert_eq!(
+            next_event(&mut stream).unwrap(),
+            HyprlandEvent::ActiveWindow {
+                address: "abc".to_string()
+            }
+        );
+        assert!(next_event(&mut stream).is_err());
+    }
+
+    fn hypr_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("qmkonnect-hyprdir-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Leaves a socket file behind with nobody listening, like a crashed compositor
+    fn dead_instance(hypr_dir: &Path, signature: &str) {
+        let dir = hypr_dir.join(signature);
+        fs::create_dir_all(&dir).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(dir.join(".socket.sock")).unwrap());
+    }
+
+    #[test]
+    fn test_select_newest_live_instance() {
+        let hypr_dir = hypr_dir("newest");
+        let _older = FakeHyprland::start_in(hypr_dir.join("aaa_1700000000_1"));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let _newer = FakeHyprland::start_in(hypr_dir.join("bbb_1700000100_2"));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        dead_instance(&hypr_dir, "ccc_1700000200_3");
+
+        let ipc = select_instance(&hypr_dir, None, None).unwrap();
+        assert_eq!(ipc.signature(), "bbb_1700000100_2");
+
+        let _ = fs::remove_dir_all(&hypr_dir);
+    }
+
+    #[test]
+    fn test_select_inherited_instance_while_alive() {
+        let hypr_dir = hypr_dir("inherited");
+        let _older = FakeHyprland::start_in(hypr_dir.join("aaa_1700000000_1"));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let _newer = FakeHyprland::start_in(hypr_dir.join("bbb_1700000100_2"));
+        dead_instance(&hypr_dir, "ccc_1700000200_3");
+
+        let ipc = select_instance(&hypr_dir, None, Some("aaa_1700000000_1")).unwrap();
+        assert_eq!(ipc.signature(), "aaa_1700000000_1");
+
+        // After a restart the inherited signature is dead
+        let ipc = select_instance(&hypr_dir, None, Some("ccc_1700000200_3")).unwrap();
+        assert_eq!(ipc.signature(), "bbb_1700000100_2");
+
+        let _ = fs::remove_dir_all(&hypr_dir);
+    }
+
+    #[test]
+    fn test_select_configured_instance() {
+        let hypr_dir = hypr_dir("configured");
+        let _older = FakeHyprland::start_in(hypr_dir.join("aaa_1700000000_1"));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let _newer = FakeHyprland::start_in(hypr_dir.join("bbb_1700000100_2"));
+        dead_instance(&hypr_dir, "ccc_1700000200_3");
+
+        let ipc = select_instance(&hypr_dir, Some("aaa_1700000000_1"), None).unwrap();
+        assert_eq!(ipc.signature(), "aaa_1700000000_1");
+
+        // A configured instance is never swapped for another one
+        assert!(select_instance(&hypr_dir, Some("ccc_1700000200_3"), None).is_err());
+
+        let _ = fs::remove_dir_all(&hypr_dir);
+    }
+
+    #[test]
+    fn test_select_without_instances() {
+        let hypr_dir = hypr_dir("none");
+        assert!(select_instance(&hypr_dir, None, None).is_err());
+        assert!(select_instance(&hypr_dir.join("missing"), None, None).is_err());
+        let _ = fs::remove_dir_all(&hypr_dir);
+    }
+
+    #[test]
+    fn test_active_window_query() {
+        let hyprland = FakeHyprland::start("query");
+        let ipc = hyprland.ipc();
+
+        assert_eq!(ipc.active_window().unwrap(), None);
+
+        hyprland.set_reply(
+            r#"{"address": "0x55d0c7a4b2f0", "class": "kitty", "initialClass": "kitty", "title": "~", "pid": 4242}"#,
+        );
+        assert_eq!(
+            ipc.active_window().unwrap(),
+            Some(ActiveWindow {
+                address: "0x55d0c7a4b2f0".to_string(),
+                initial_class: "kitty".to_string(),
+                title: "~".to_string(),
+                fullscreen: false,
+                workspace: Workspace::default(),
+                floating: false,
+                pid: Some(4242),
+            })
+        );
+
+        // No process to speak of
+        hyprland.set_reply(
+            r#"{"address": "0x55d0c7a4b2f0", "initialClass": "", "title": "", "pid": -1}"#,
+        );
+        assert_eq!(ipc.active_window().unwrap().unwrap().pid, None);
+
+        // Fullscreen is a mode number on current releases and a bool on older ones
+        hyprland.set_reply(
+            r#"{"address": "0x55d0c7a4b2f0", "initialClass": "steam_app_570", "title": "Dota 2", "fullscreen": 2, "workspace": {"id": 9, "name": "games"}}"#,
+        );
+        let active_window = ipc.active_window().unwrap().unwrap();
+        assert!(active_window.fullscreen);
+        assert_eq!(
+            active_window.workspace,
+            Workspace {
+                id: 9,
+                name: "games".to_string(),
+            }
+        );
+        hyprland.set_reply(
+            r#"{"address": "0x55d0c7a4b2f0", "initialClass": "steam_app_570", "title": "Dota 2", "fullscreen": true}"#,
+        );
+        assert!(ipc.active_window().unwrap().unwrap().fullscreen);
+    }
+
+    #[test]
+    fn test_active_layout_query() {
+        let hyprland = FakeHyprland::start("devices");
+        let ipc = hyprland.ipc();
+
+        hyprland.set_reply(
+            r#"{"mice": [], "keyboards": [
+                {"name": "video-bus", "active_keymap": "English (US)", "main": false},
+                {"name": "at-translated-set-2-keyboard", "active_keymap": "German", "main": true}
+            ]}"#,
+        );
+        assert_eq!(ipc.active_layout().unwrap(), Some("German".to_string()));
+
+        // Releases without "main" fall back to the first keyboard
+        hyprland.set_reply(
+            r#"{"keyboards": [{"name": "at-translated-set-2-keyboard", "active_keymap": "English (US)"}]}"#,
+        );
+        assert_eq!(
+            ipc.active_layout().unwrap(),
+            Some("English (US)".to_string())
+        );
+
+        hyprland.set_reply(r#"{"keyboards": []}"#);
+        assert_eq!(ipc.active_layout().unwrap(), None);
+    }
+
+    #[test]
+    fn test_client_class_query() {
+        let hyprland = FakeHyprland::start("clients");
+        let ipc = hyprland.ipc();
+
+        hyprland.set_reply(
+            r#"[
+                {"address": "0x55d0c7a4b2f0", "class": "kitty", "initialClass": "kitty", "title": "~"},
+                {"address": "0x55d0c7a4c310", "class": "Slack", "initialClass": "Slack", "title": "Slack"}
+            ]"#,
+        );
+        assert_eq!(
+            ipc.client_class("55d0c7a4c310").unwrap(),
+            Some("Slack".to_string())
+        );
+        assert_eq!(ipc.client_class("abc").unwrap(), None);
+    }
+
+    #[test]
+    fn test_active_workspace_query() {
+        let hyprland = FakeHyprland::start("workspace");
+        let ipc = hyprland.ipc();
+
+        hyprland.set_reply(r#"{"id": -98, "name": "special:scratchpad", "windows": 1}"#);
+        assert_eq!(
+            ipc.active_workspace().unwrap(),
+            Some(Workspace {
+                id: -98,
+                name: "special:scratchpad".to_string(),
+            })
+        );
+
+        hyprland.set_reply("{}");
+        assert_eq!(ipc.active_workspace().unwrap(), None);
+    }
+}
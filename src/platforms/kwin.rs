@@ -0,0 +1,357 @@
+#![cfg(all(target_os = "linux", feature = "kwin"))]
+use crate::core::types::WindowInfo;
+use crate::platforms::dbus_callback::{self, BUS_NAME};
+use crate::platforms::WindowMonitor;
+use once_cell::sync::Lazy;
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+};
+use zbus::blocking::{connection, Connection};
+use zbus::interface;
+
+// Object the KWin script calls back into
+const CALLBACK_PATH: &str = "/io/github/qmkonnect/KWin";
+
+// KWin's scripting service
+const KWIN_SERVICE: &str = "org.kde.KWin";
+const SCRIPTING_PATH: &str = "/Scripting";
+const SCRIPTING_INTERFACE: &str = "org.kde.kwin.Scripting";
+const SCRIPT_PLUGIN_NAME: &str = "qmkonnect";
+
+// Loaded into KWin through org.kde.kwin.Scripting. Reports the active window
+// whenever it changes, and again whenever its caption changes.
+const KWIN_SCRIPT: &str = r#"function report(window) {
+    callDBus("io.github.qmkonnect", "/io/github/qmkonnect/KWin",
+             "io.github.qmkonnect.KWin", "WindowActivated",
+             window ? String(window.resourceClass) : "",
+             window ? String(window.caption) : "");
+}
+
+var tracked = null;
+
+function onCaptionChanged() {
+    report(tracked);
+}
+
+function onWindowActivated(window) {
+    if (tracked) {
+        try {
+            tracked.captionChanged.disconnect(onCaptionChanged);
+        } catch (e) {
+            // The previous window is already gone
+        }
+    }
+    tracked = window;
+    if (tracked) {
+        tracked.captionChanged.connect(onCaptionChanged);
+    }
+    report(window);
+}
+
+// Plasma 6 renamed clientActivated/activeClient to windowActivated/activeWindow
+(workspace.windowActivated || workspace.clientActivated).connect(onWindowActivated);
+onWindowActivated(workspace.activeWindow || workspace.activeClient);
+"#;
+
+// D-Bus object the KWin script calls on every activation
+struct KWinCallback {
+    sender: mpsc::Sender<WindowInfo>,
+}
+
+#[interface(name = "io.github.qmkonnect.KWin")]
+impl KWinCallback {
+    fn window_activated(&self, resource_class: String, caption: String) {
+        // The receiver only goes away when the monitor is shutting down
+        let _ = self.sender.send(WindowInfo::new(resource_class, caption));
+    }
+}
+
+// Connection the script was loaded over. Kept out of the monitor, which is
+// busy in start() until it goes away, so Ctrl+C can unload the script too.
+static SCRIPT_CONNECTION: Lazy<Mutex<Option<Connection>>> = Lazy::new(|| Mutex::new(None));
+
+pub struct KWinMonitor {
+    verbose: bool,
+}
+
+impl KWinMonitor {
+    pub fn new(verbose: bool) -> Self {
+        Self { verbose }
+    }
+
+    fn run(&self, builder: connection::Builder, script_dir: &Path) -> Result<(), Box<dyn Error>> {
+        let (sender, receiver) = mpsc::channel();
+        let connection = serve_callback(builder, sender)
+            .map_err(|e| format!("Failed to register {} on the session bus: {}", BUS_NAME, e))?;
+
+        let script_path = write_script(script_dir)?;
+        load_script(&connection, &script_path)
+            .map_err(|e| format!("Failed to load KWin script: {}\nAre you sure KWin is running?", e))?;
+
+        if self.verbose {
+            println!("Loaded KWin script from {}", script_path.display());
+        }
+
+        *SCRIPT_CONNECTION.lock().unwrap() = Some(connection);
+
+        // Blocks for as long as the connection (and with it the sender) is alive
+        dbus_callback::forward_windows(receiver, self.verbose);
+
+        Ok(())
+    }
+}
+
+impl WindowMonitor for KWinMonitor {
+    fn platform_name(&self) -> &str {
+        "KDE Plasma (KWin)"
+    }
+
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.verbose {
+            println!("Starting KWin window monitor");
+        }
+
+        self.run(connection::Builder::session()?, &crate::core::runtime_dir()?)
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        unload_kwin_script()
+    }
+}
+
+// Unload the script and let start() return, KWin would keep running it otherwise
+pub fn unload_kwin_script() -> Result<(), Box<dyn Error>> {
+    let connection = SCRIPT_CONNECTION.lock().unwrap().take();
+    if let Some(connection) = connection {
+        unload_script(&connection)?;
+    }
+    Ok(())
+}
+
+// Detect a Plasma session, where the KWin script is the only reliable source
+pub fn is_kwin_session() -> bool {
+    env::var("XDG_CURRENT_DESKTOP")
+        .map(|desktop| desktop.split(':').any(|d| d.eq_ignore_ascii_case("KDE")))
+        .unwrap_or(false)
+}
+
+fn serve_callback(
+    builder: connection::Builder,
+    sender: mpsc::Sender<WindowInfo>,
+) -> zbus::Result<Connection> {
+    builder
+        .name(BUS_NAME)?
+        .serve_at(CALLBACK_PATH, KWinCallback { sender })?
+        .build()
+}
+
+fn write_script(dir: &Path) -> std::io::Result<PathBuf> {
+    let script_path = dir.join("kwin-script.js");
+    fs::write(&script_path, KWIN_SCRIPT)?;
+    Ok(script_path)
+}
+
+fn load_script(connection: &Connection, script_path: &Path) -> zbus::Result<()> {
+    // A previous run may have been killed without unloading its script
+    unload_script(connection)?;
+
+    let reply = connection.call_method(
+        Some(KWIN_SERVICE),
+        SCRIPTING_PATH,
+        Some(SCRIPTING_INTERFACE),
+        "loadScript",
+        &(script_path.to_string_lossy().as_ref(), SCRIPT_PLUGIN_NAME),
+    )?;
+    let script_id: i32 = reply.body().deserialize()?;
+    if script_id < 0 {
+        return Err(zbus::Error::Failure(format!(
+            "KWin rejected script {}",
+            script_path.display()
+        )));
+    }
+
+    // Runs every loaded script that isn't running yet
+    connection.call_method(
+        Some(KWIN_SERVICE),
+        SCRIPTING_PATH,
+        Some(SCRIPTING_INTERFACE),
+        "start",
+        &(),
+    )?;
+
+    Ok(())
+}
+
+fn unload_script(connection: &Connection) -> zbus::Result<bool> {
+    let reply = connection.call_method(
+        Some(KWIN_SERVICE),
+        SCRIPTING_PATH,
+        Some(SCRIPTING_INTERFACE),
+        "unloadScript",
+        &SCRIPT_PLUGIN_NAME,
+    )?;
+    reply.body().deserialize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platforms::dbus_callback::PrivateBus;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    // Stand-in for KWin that records what the monitor asks it to do
+    #[derive(Default)]
+    struct FakeScripting {
+        loaded: Arc<Mutex<Vec<(String, String)>>>,
+        scripts: Arc<Mutex<Vec<String>>>,
+        unloaded: Arc<Mutex<Vec<String>>>,
+        started: Arc<Mutex<bool>>,
+    }
+
+    #[interface(name = "org.kde.kwin.Scripting")]
+    impl FakeScripting {
+        #[zbus(name = "loadScript")]
+        fn load_script(&self, file_path: String, plugin_name: String) -> i32 {
+            // Read right away, like KWin does
+            let script = fs::read_to_string(&file_path).unwrap_or_default();
+            self.scripts.lock().unwrap().push(script);
+            let mut loaded = self.loaded.lock().unwrap();
+            loaded.push((file_path, plugin_name));
+            loaded.len() as i32
+        }
+
+        #[zbus(name = "unloadScript")]
+        fn unload_script(&self, plugin_name: String) -> bool {
+            self.unloaded.lock().unwrap().push(plugin_name);
+            false
+        }
+
+        #[zbus(name = "start")]
+        fn start(&self) {
+            *self.started.lock().unwrap() = true;
+        }
+    }
+
+    // Service, path, interface and method of the script's callDBus()
+    fn callback_of(script: &str) -> Vec<String> {
+        let (_, call) = script.split_once("callDBus(").unwrap();
+        call.split('"')
+            .skip(1)
+            .step_by(2)
+            .take(4)
+            .map(String::from)
+            .collect()
+    }
+
+    fn serve_fake(bus: &PrivateBus, fake: FakeScripting) -> Connection {
+        bus.builder()
+            .name(KWIN_SERVICE)
+            .unwrap()
+            .serve_at(SCRIPTING_PATH, fake)
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn script_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("qmkonnect-kwin-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_load_script_and_receive_callbacks() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let fake = FakeScripting::default();
+        let loaded = Arc::clone(&fake.loaded);
+        let scripts = Arc::clone(&fake.scripts);
+        let unloaded = Arc::clone(&fake.unloaded);
+        let started = Arc::clone(&fake.started);
+        let kwin = serve_fake(&bus, fake);
+
+        let (sender, receiver) = mpsc::channel();
+        let connection = serve_callback(bus.builder(), sender).unwrap();
+
+        let script_dir = script_dir("load");
+        let script_path = write_script(&script_dir).unwrap();
+        load_script(&connection, &script_path).unwrap();
+
+        assert_eq!(
+            *loaded.lock().unwrap(),
+            vec![(
+                script_path.to_string_lossy().to_string(),
+                SCRIPT_PLUGIN_NAME.to_string()
+            )]
+        );
+        assert_eq!(*unloaded.lock().unwrap(), vec![SCRIPT_PLUGIN_NAME.to_string()]);
+        assert!(*started.lock().unwrap());
+
+        // The script calls back into what serve_callback() registered
+        let callback = callback_of(&scripts.lock().unwrap()[0]);
+        assert_eq!(callback, [BUS_NAME, CALLBACK_PATH, "io.github.qmkonnect.KWin", "WindowActivated"]);
+        kwin.call_method(
+            Some(callback[0].as_str()),
+            callback[1].as_str(),
+            Some(callback[2].as_str()),
+            callback[3].as_str(),
+            &("org.kde.konsole", "~ : bash — Konsole"),
+        )
+        .unwrap();
+
+        let window_info = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            window_info,
+            WindowInfo::new("org.kde.konsole".to_string(), "~ : bash — Konsole".to_string())
+        );
+
+        let _ = fs::remove_dir_all(&script_dir);
+    }
+
+    #[test]
+    fn test_stop_unloads_script() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let fake = FakeScripting::default();
+        let loaded = Arc::clone(&fake.loaded);
+        let unloaded = Arc::clone(&fake.unloaded);
+        let _kwin = serve_fake(&bus, fake);
+
+        let script_dir = script_dir("stop");
+        let (done, finished) = mpsc::channel();
+        let builder = bus.builder();
+        let dir = script_dir.clone();
+        std::thread::spawn(move || {
+            let result = KWinMonitor::new(false).run(builder, &dir);
+            let _ = done.send(result.map_err(|e| e.to_string()));
+        });
+
+        // Running once the script is loaded
+        for _ in 0..500 {
+            if SCRIPT_CONNECTION.lock().unwrap().is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(loaded.lock().unwrap().len(), 1);
+        assert!(finished.try_recv().is_err());
+
+        // What stop() and Ctrl+C do
+        unload_kwin_script().unwrap();
+        assert_eq!(*unloaded.lock().unwrap(), vec![SCRIPT_PLUGIN_NAME.to_string(); 2]);
+        finished.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+
+        let _ = fs::remove_dir_all(&script_dir);
+    }
+}
@@ -1,4 +1,8 @@
+pub(crate) mod dbus_callback;
+mod gnome;
 mod hyprland;
+mod hyprland_ipc;
+mod kwin;
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
@@ -8,7 +12,6 @@ mod windows;
 mod x11;
 
 // Define WindowMonitor trait
-#[cfg(not(all(target_os = "linux", feature = "hyprland")))]
 pub trait WindowMonitor: Send {
     fn platform_name(&self) -> &str;
     fn start(&mut self) -> Result<(), Box<dyn std::error::Error>>;
@@ -20,28 +23,39 @@ pub trait WindowMonitor: Send {
         Ok(())
     }
 }
-#[cfg(all(target_os = "linux", feature = "hyprland"))]
-pub trait WindowMonitor {
-    fn platform_name(&self) -> &str;
-    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>>;
-
-    // Add attribute to suppress dead code warning
-    #[allow(dead_code)]
-    fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // Default implementation
-        Ok(())
-    }
-}
 
 // Export Linux module's functions
 #[cfg(target_os = "linux")]
 pub use linux::*;
 
+#[cfg(all(target_os = "linux", feature = "gnome"))]
+pub use gnome::install_gnome_extension;
+
+#[cfg(all(target_os = "linux", feature = "kwin"))]
+pub use kwin::unload_kwin_script;
+
 use std::error::Error;
 
 // Return a platform-specific monitor implementation
+#[allow(clippy::needless_return)]
 pub fn create_monitor(verbose: bool) -> Result<Box<dyn WindowMonitor>, Box<dyn Error>> {
     // Platform-specific implementations
+    #[cfg(all(target_os = "linux", feature = "kwin"))]
+    {
+        if kwin::is_kwin_session() {
+            use kwin::KWinMonitor;
+            return Ok(Box::new(KWinMonitor::new(verbose)));
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "gnome"))]
+    {
+        if gnome::is_gnome_session() {
+            use gnome::GnomeMonitor;
+            return Ok(Box::new(GnomeMonitor::new(verbose)));
+        }
+    }
+
     #[cfg(all(target_os = "linux", feature = "hyprland"))]
     {
         use hyprland::HyprlandMonitor;
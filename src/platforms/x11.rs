@@ -1,12 +1,35 @@
 #![cfg(all(target_os = "linux", not(feature = "hyprland")))]
 use crate::core::notifier;
-use crate::core::types::WindowInfo;
+use crate::core::types::{HostEvent, WindowInfo};
 use crate::platforms::WindowMonitor;
+use std::collections::HashSet;
 use std::error::Error;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_STATE,
+        _NET_WM_STATE_DEMANDS_ATTENTION,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        UTF8_STRING,
+    }
+}
 
 pub struct X11Monitor {
     verbose: bool,
@@ -20,28 +43,6 @@ impl X11Monitor {
             running: Arc::new(AtomicBool::new(false)),
         }
     }
-
-    fn get_active_window_info(&self) -> Result<Option<WindowInfo>, Box<dyn Error>> {
-        // For now, create a basic implementation that provides a working foundation
-        // This can be enhanced later with proper X11 window detection using xprop or other tools
-        
-        // Try to get window information using system commands as a fallback
-        if let Ok(output) = std::process::Command::new("xprop")
-            .args(["-root", "_NET_ACTIVE_WINDOW"])
-            .output()
-        {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            if output_str.contains("window id") {
-                // We have an active window, create a basic window info
-                Ok(Some(WindowInfo::new("X11Application".to_string(), "Active Window".to_string())))
-            } else {
-                Ok(Some(WindowInfo::new("Linux".to_string(), "Desktop".to_string())))
-            }
-        } else {
-            // Fallback: create a generic window info that indicates Linux is running
-            Ok(Some(WindowInfo::new("Linux".to_string(), "Desktop".to_string())))
-        }
-    }
 }
 
 impl WindowMonitor for X11Monitor {
@@ -58,43 +59,69 @@ impl WindowMonitor for X11Monitor {
 
         let running = Arc::clone(&self.running);
         let verbose = self.verbose;
-        
+
         // Start polling thread to check for window changes
         thread::spawn(move || {
-            let mut last_window_info: Option<(String, String)> = None;
-            
+            let (conn, root, atoms) = match connect() {
+                Ok(connected) => connected,
+                Err(e) => {
+                    eprintln!("Failed to connect to the X server: {}", e);
+                    return;
+                }
+            };
+            let mut last_window_info: Option<WindowInfo> = None;
+
             while running.load(Ordering::SeqCst) {
-                // Create a temporary monitor instance for getting window info
-                let temp_monitor = X11Monitor::new(verbose);
-                
-                if let Ok(Some(window_info)) = temp_monitor.get_active_window_info() {
-                    let current_window = (window_info.app_class.clone(), window_info.title.clone());
-                    
+                match active_window_info(&conn, root, &atoms) {
                     // Check if window changed
-                    if last_window_info.as_ref() != Some(&current_window) {
+                    Ok(window_info) if last_window_info.as_ref() != Some(&window_info) => {
                         if verbose {
-                            println!("Window changed - Class: '{}', Title: '{}'", 
-                                window_info.app_class, window_info.title);
+                            println!(
+                                "Window changed - Class: '{}', Title: '{}'",
+                                window_info.app_class, window_info.title
+                            );
                         }
-                        
+
                         // Notify QMK
                         if let Err(e) = notifier::notify_qmk(&window_info, verbose) {
                             eprintln!("Failed to notify QMK: {}", e);
                         }
-                        
-                        last_window_info = Some(current_window);
+
+                        last_window_info = Some(window_info);
+                    }
+                    Ok(_) => {}
+                    // The window may close between two requests
+                    Err(e) => {
+                        if verbose {
+                            println!("Failed to read active window: {}", e);
+                        }
                     }
                 }
-                
+
                 // Poll every 100ms
                 thread::sleep(Duration::from_millis(100));
             }
-            
+
             if verbose {
                 println!("Linux X11 monitor thread stopped");
             }
         });
 
+        // Layout switches arrive as XKB events on their own connection
+        let running = Arc::clone(&self.running);
+        thread::spawn(move || {
+            if let Err(e) = watch_layout(&running, verbose) {
+                eprintln!("Keyboard layout changes will not be reported: {}", e);
+            }
+        });
+
+        let running = Arc::clone(&self.running);
+        thread::spawn(move || {
+            if let Err(e) = watch_attention(&running, verbose) {
+                eprintln!("Windows asking for attention will not be reported: {}", e);
+            }
+        });
+
         if self.verbose {
             println!("Linux X11 monitor started - polling for window changes");
         }
@@ -106,8 +133,310 @@ impl WindowMonitor for X11Monitor {
         if self.verbose {
             println!("Stopping Linux X11 window monitor");
         }
-        
+
         self.running.store(false, Ordering::SeqCst);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Report the active XKB group by name, e.g. "English (US)", whenever it changes
+fn watch_layout(running: &AtomicBool, verbose: bool) -> Result<(), Box<dyn Error>> {
+    let (conn, _) = x11rb::connect(None)?;
+    let device = xkb::ID::USE_CORE_KBD.into();
+
+    let version = conn.xkb_use_extension(1, 0)?.reply()?;
+    if !version.supported {
+        return Err("the X server does not support XKB".into());
+    }
+
+    conn.xkb_select_events(
+        device,
+        xkb::EventType::from(0u16),
+        xkb::EventType::STATE_NOTIFY,
+        xkb::MapPart::from(0u16),
+        xkb::MapPart::from(0u16),
+        &xkb::SelectEventsAux::new(),
+    )?;
+    conn.flush()?;
+
+    // Events only describe changes, so report the current group first
+    let state = conn.xkb_get_state(device)?.reply()?;
+    report_layout(&conn, device, u8::from(state.group), verbose)?;
+
+    while running.load(Ordering::SeqCst) {
+        if let Event::XkbStateNotify(event) = conn.wait_for_event()? {
+            if event.changed.contains(xkb::StatePart::GROUP_STATE) {
+                report_layout(&conn, device, u8::from(event.group), verbose)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn report_layout(
+    conn: &impl Connection,
+    device: xkb::DeviceSpec,
+    group: u8,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    // Group names are looked up every time as setxkbmap can replace them at any point
+    let names = conn
+        .xkb_get_names(device, xkb::NameDetail::GROUP_NAMES)?
+        .reply()?;
+    let atom: Option<Atom> = names
+        .value_list
+        .groups
+        .and_then(|groups| groups.get(usize::from(group)).copied());
+    let Some(atom) = atom else {
+        return Ok(());
+    };
+    let layout = String::from_utf8_lossy(&conn.get_atom_name(atom)?.reply()?.name).to_string();
+
+    if verbose {
+        println!("Keyboard layout switched to {}", layout);
+    }
+    if let Err(e) = notifier::notify_event(&HostEvent::Layout(layout), verbose) {
+        eprintln!("Failed to notify QMK: {}", e);
+    }
+
+    Ok(())
+}
+
+// Report the class of every window that sets _NET_WM_STATE_DEMANDS_ATTENTION
+fn watch_attention(running: &AtomicBool, verbose: bool) -> Result<(), Box<dyn Error>> {
+    let (conn, root, atoms) = connect()?;
+
+    // Property changes on the root window tell us about new clients, those on
+    // the clients about their state
+    let watch = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+    conn.change_window_attributes(root, &watch)?;
+    let mut watched = HashSet::new();
+    let mut urgent = HashSet::new();
+    watch_clients(&conn, root, &atoms, &mut watched)?;
+    conn.flush()?;
+
+    while running.load(Ordering::SeqCst) {
+        // Errors about windows that are already gone end up here too
+        let Event::PropertyNotify(event) = conn.wait_for_event()? else {
+            continue;
+        };
+
+        if event.window == root && event.atom == atoms._NET_CLIENT_LIST {
+            watch_clients(&conn, root, &atoms, &mut watched)?;
+            urgent.retain(|window| watched.contains(window));
+            conn.flush()?;
+        } else if event.atom == atoms._NET_WM_STATE {
+            let Ok(wants_attention) = has_state(
+                &conn,
+                event.window,
+                &atoms,
+                atoms._NET_WM_STATE_DEMANDS_ATTENTION,
+            ) else {
+                continue;
+            };
+
+            // Only the moment a window starts asking is reported
+            if !wants_attention {
+                urgent.remove(&event.window);
+            } else if urgent.insert(event.window) {
+                let app_class = window_class(&conn, event.window).unwrap_or_default();
+                if verbose {
+                    println!("{} wants attention", app_class);
+                }
+                if let Err(e) = notifier::notify_event(&HostEvent::Attention(app_class), verbose) {
+                    eprintln!("Failed to notify QMK: {}", e);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn connect() -> Result<(RustConnection, Window, Atoms), Box<dyn Error>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let atoms = Atoms::new(&conn)?.reply()?;
+    Ok((conn, root, atoms))
+}
+
+// The focused window according to the window manager's EWMH hints
+fn active_window_info(
+    conn: &impl Connection,
+    root: Window,
+    atoms: &Atoms,
+) -> Result<WindowInfo, Box<dyn Error>> {
+    let active_window = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )?
+        .reply()?
+        .value32()
+        .and_then(|mut windows| windows.next())
+        .filter(|window| *window != 0);
+
+    // Nothing focused, e.g. on an empty desktop
+    let Some(window) = active_window else {
+        return Ok(WindowInfo::new(String::new(), String::new()));
+    };
+
+    let mut window_info = WindowInfo::new(
+        window_class(conn, window)?,
+        window_title(conn, window, atoms)?,
+    );
+    window_info.fullscreen = has_state(conn, window, atoms, atoms._NET_WM_STATE_FULLSCREEN)?;
+    window_info.transient = is_transient(conn, window, atoms)?;
+    window_info.pid = conn
+        .get_property(false, window, atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)?
+        .reply()?
+        .value32()
+        .and_then(|mut pids| pids.next());
+    Ok(window_info)
+}
+
+// Dialogs and windows that name the window they belong to
+fn is_transient(
+    conn: &impl Connection,
+    window: Window,
+    atoms: &Atoms,
+) -> Result<bool, Box<dyn Error>> {
+    let transient_for = conn
+        .get_property(
+            false,
+            window,
+            AtomEnum::WM_TRANSIENT_FOR,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )?
+        .reply()?
+        .value32()
+        .and_then(|mut windows| windows.next())
+        .unwrap_or(0);
+    if transient_for != 0 {
+        return Ok(true);
+    }
+
+    let window_type = conn
+        .get_property(
+            false,
+            window,
+            atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            0,
+            16,
+        )?
+        .reply()?;
+    Ok(window_type
+        .value32()
+        .is_some_and(|mut types| types.any(|atom| atom == atoms._NET_WM_WINDOW_TYPE_DIALOG)))
+}
+
+fn window_title(
+    conn: &impl Connection,
+    window: Window,
+    atoms: &Atoms,
+) -> Result<String, Box<dyn Error>> {
+    let title = conn
+        .get_property(
+            false,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            0,
+            1024,
+        )?
+        .reply()?;
+    if !title.value.is_empty() {
+        return Ok(String::from_utf8_lossy(&title.value).to_string());
+    }
+
+    // Fall back to the legacy Latin-1 name
+    let title = conn
+        .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?
+        .reply()?;
+    Ok(title.value.iter().map(|byte| char::from(*byte)).collect())
+}
+
+// Whether _NET_WM_STATE lists the given state
+fn has_state(
+    conn: &impl Connection,
+    window: Window,
+    atoms: &Atoms,
+    state: Atom,
+) -> Result<bool, Box<dyn Error>> {
+    let states = conn
+        .get_property(false, window, atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 64)?
+        .reply()?;
+    Ok(states
+        .value32()
+        .is_some_and(|mut states| states.any(|atom| atom == state)))
+}
+
+// Listen for property changes on every client we haven't seen yet
+fn watch_clients(
+    conn: &impl Connection,
+    root: Window,
+    atoms: &Atoms,
+    watched: &mut HashSet<Window>,
+) -> Result<(), Box<dyn Error>> {
+    let clients: HashSet<Window> = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_CLIENT_LIST,
+            AtomEnum::WINDOW,
+            0,
+            u32::MAX,
+        )?
+        .reply()?
+        .value32()
+        .map(|windows| windows.collect())
+        .unwrap_or_default();
+
+    let watch = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+    for window in clients.difference(watched) {
+        conn.change_window_attributes(*window, &watch)?;
+    }
+
+    *watched = clients;
+    Ok(())
+}
+
+fn window_class(conn: &impl Connection, window: Window) -> Result<String, Box<dyn Error>> {
+    let wm_class = conn
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?
+        .reply()?;
+    Ok(parse_wm_class(&wm_class.value))
+}
+
+// WM_CLASS holds the instance and class names, each terminated by a NUL byte
+fn parse_wm_class(value: &[u8]) -> String {
+    let mut names = value
+        .split(|byte| *byte == 0)
+        .map(|name| String::from_utf8_lossy(name).to_string());
+    let instance = names.next().unwrap_or_default();
+
+    names
+        .next()
+        .filter(|class| !class.is_empty())
+        .unwrap_or(instance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wm_class() {
+        assert_eq!(parse_wm_class(b"slack\0Slack\0"), "Slack");
+        assert_eq!(parse_wm_class(b"xterm\0"), "xterm");
+        assert_eq!(parse_wm_class(b""), "");
+    }
+}
@@ -25,9 +25,17 @@ impl PlatformRunner for LinuxRunner {
             println!("Using platform: {}", monitor.platform_name());
         }
 
+        // Lets `qmkonnect ctl` talk to us
+        crate::control::start(monitor.platform_name(), self.verbose);
+        crate::runners::handle_pause_signal(self.verbose)?;
+
         // Set up signal handling for immediate exit
         ctrlc::set_handler(move || {
             println!("\nReceived Ctrl+C, shutting down...");
+            #[cfg(feature = "kwin")]
+            if let Err(e) = platforms::unload_kwin_script() {
+                eprintln!("Error unloading KWin script: {}", e);
+            }
             // Force immediate exit - no waiting or additional complexity
             process::exit(0);
         })?;
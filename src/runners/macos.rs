@@ -25,6 +25,10 @@ impl PlatformRunner for MacOSRunner {
             println!("Using platform: {}", monitor.platform_name());
         }
 
+        // Lets `qmkonnect ctl` talk to us
+        crate::control::start(monitor.platform_name(), self.verbose);
+        crate::runners::handle_pause_signal(self.verbose)?;
+
         // Set up signal handling for immediate exit
         ctrlc::set_handler(move || {
             println!("\nReceived Ctrl+C, shutting down...");
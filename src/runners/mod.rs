@@ -14,6 +14,22 @@ pub trait PlatformRunner {
     fn run(&mut self, args: &[String]) -> Result<(), Box<dyn Error>>;
 }
 
+// SIGUSR1 toggles pause, e.g. from a compositor keybind running `pkill -USR1 qmkonnect`
+#[cfg(unix)]
+pub fn handle_pause_signal(verbose: bool) -> Result<(), Box<dyn Error>> {
+    use signal_hook::{consts::SIGUSR1, iterator::Signals};
+
+    let mut signals = Signals::new([SIGUSR1])?;
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            if let Err(e) = crate::core::notifier::toggle_paused(verbose) {
+                eprintln!("Error notifying QMK: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
 // Create platform-specific runner
 pub fn create_runner(verbose: bool) -> Result<Box<dyn PlatformRunner>, Box<dyn Error>> {
     #[cfg(target_os = "windows")]
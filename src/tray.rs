@@ -1,14 +1,20 @@
 #![cfg(not(all(target_os = "linux", feature = "hyprland")))]
 use tao::{
-    event::Event,
+    event::{Event, StartCause},
     event_loop::{ControlFlow, EventLoopBuilder},
 };
 
 use tray_icon::{
-    menu::{AboutMetadata, Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    menu::{
+        AboutMetadata, CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu,
+    },
     TrayIconBuilder,
 };
 
+use crate::core::notifier;
+use crate::core::types::WindowInfo;
+use std::time::{Duration, Instant};
+
 #[cfg(target_os = "macos")]
 mod objc_types {
     #[repr(C)]
@@ -61,6 +67,14 @@ pub fn setup_tray() {
 
     let tray_menu = Menu::new();
 
+    let pause_i = CheckMenuItem::new("Pause", true, notifier::is_paused(), None);
+
+    // Recently sent contexts to pin, filled in as they come
+    let pin_m = Submenu::new("Pin Context", true);
+    let unpin_i = MenuItem::new("Follow Focus", notifier::pinned().is_some(), None);
+    let _ = pin_m.append_items(&[&unpin_i, &PredefinedMenuItem::separator()]);
+    let mut recent: Vec<(MenuItem, WindowInfo)> = Vec::new();
+
     let settings_i = MenuItem::new("Settings", true, None);
     let quit_i = MenuItem::new("Quit", true, None);
     let _ = tray_menu.append_items(&[
@@ -73,6 +87,8 @@ pub fn setup_tray() {
             }),
         ),
         &PredefinedMenuItem::separator(),
+        &pause_i,
+        &pin_m,
         &settings_i,
         &PredefinedMenuItem::separator(),
         &quit_i,
@@ -81,10 +97,28 @@ pub fn setup_tray() {
     let mut tray_icon = None;
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
+        // Wake up now and then to follow pauses from the CLI or SIGUSR1
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_secs(1));
 
         match event {
-            Event::NewEvents(tao::event::StartCause::Init) => {
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                pause_i.set_checked(notifier::is_paused());
+                unpin_i.set_enabled(notifier::pinned().is_some());
+
+                let contexts = notifier::recent_contexts();
+                if !recent.iter().map(|(_, window)| window).eq(contexts.iter()) {
+                    for (item, _) in recent.drain(..) {
+                        let _ = pin_m.remove(&item);
+                    }
+                    for window_info in contexts {
+                        let item = MenuItem::new(context_label(&window_info), true, None);
+                        let _ = pin_m.append(&item);
+                        recent.push((item, window_info));
+                    }
+                }
+            }
+
+            Event::NewEvents(StartCause::Init) => {
                 let icon = {
                     #[cfg(target_os = "macos")]
                     {
@@ -130,7 +164,27 @@ pub fn setup_tray() {
             }
 
             Event::UserEvent(UserEvent::MenuEvent(event)) => {
-                if event.id == settings_i.id() {
+                if event.id == pause_i.id() {
+                    // Resuming sends the current window right away
+                    let paused = !notifier::is_paused();
+                    if let Err(e) = notifier::set_paused(paused, false) {
+                        eprintln!("Error notifying QMK: {}", e);
+                    }
+                    pause_i.set_checked(paused);
+                } else if event.id == unpin_i.id() {
+                    if let Err(e) = notifier::clear_pin(false) {
+                        eprintln!("Error notifying QMK: {}", e);
+                    }
+                    unpin_i.set_enabled(false);
+                } else if let Some((_, window_info)) =
+                    recent.iter().find(|(item, _)| event.id == item.id())
+                {
+                    // Pinned until "Follow Focus"
+                    if let Err(e) = notifier::pin_context(window_info.clone(), None, false) {
+                        eprintln!("Error notifying QMK: {}", e);
+                    }
+                    unpin_i.set_enabled(true);
+                } else if event.id == settings_i.id() {
                     handle_settings_click();
                 } else if event.id == quit_i.id() {
                     println!("Exited");
@@ -144,6 +198,20 @@ pub fn setup_tray() {
     });
 }
 
+// Class and title, short enough for a menu
+fn context_label(window_info: &WindowInfo) -> String {
+    let label = if window_info.title.is_empty() {
+        window_info.app_class.clone()
+    } else {
+        format!("{} - {}", window_info.app_class, window_info.title)
+    };
+    if label.chars().count() > 48 {
+        format!("{}...", label.chars().take(45).collect::<String>())
+    } else {
+        label
+    }
+}
+
 fn load_icon(path: &std::path::Path) -> Result<tray_icon::Icon, Box<dyn std::error::Error>> {
     let (icon_rgba, icon_width, icon_height) = {
         let image = image::open(path)?.into_rgba8();
@@ -244,12 +312,7 @@ fn show_settings_dialog(config_path: &std::path::Path) -> Result<(), Box<dyn std
     // Load current configuration
     let current_config = match crate::core::parse_config(config_path) {
         Ok(config) => config,
-        Err(_) => {
-            crate::core::Config {
-                vendor_id: 0xfeed,
-                product_id: 0x0000,
-            }
-        }
+        Err(_) => crate::core::Config::default(),
     };
 
     // Shared state for the dialog
@@ -345,13 +408,8 @@ fn show_settings_dialog(config_path: &std::path::Path) -> Result<(), Box<dyn std
         let result = dialog_result.lock().unwrap().clone();
         
         if let Some((vendor_id, product_id)) = result {
-            // Save to file
-            let config_content = format!(
-                "# QMKonnect Configuration\n\n# Your QMK keyboard's vendor ID (in hex)\nvendor_id = 0x{:04x}\n\n# Your QMK keyboard's product ID (in hex)\nproduct_id = 0x{:04x}\n\n# Add any other configuration options here\n",
-                vendor_id, product_id
-            );
-
-            std::fs::write(config_path, config_content)?;
+            // Save to file, keeping the rest of the configuration
+            crate::core::save_device_ids(config_path, vendor_id, product_id)?;
 
             // Configuration saved successfully - no success dialog needed
             // The QMK connection is established fresh for each notification,
@@ -659,10 +717,7 @@ fn show_settings_dialog_with_pool(config_path: &std::path::Path) -> Result<(), B
         },
         Err(e) => {
             println!("DEBUG: Failed to load config, using defaults: {}", e);
-            crate::core::Config {
-                vendor_id: 0xfeed,
-                product_id: 0x0000,
-            }
+            crate::core::Config::default()
         }
     };
 
@@ -783,14 +838,9 @@ fn show_settings_dialog_with_pool(config_path: &std::path::Path) -> Result<(), B
                 (Ok(vendor_id), Ok(product_id)) => {
                     println!("DEBUG: Successfully parsed values - vendor_id: 0x{:04x}, product_id: 0x{:04x}", vendor_id, product_id);
                     
-                    // Save to file
-                    let config_content = format!(
-                        "# QMKonnect Configuration\n\n# Your QMK keyboard's vendor ID (in hex)\nvendor_id = 0x{:04x}\n\n# Your QMK keyboard's product ID (in hex)\nproduct_id = 0x{:04x}\n\n# Add any other configuration options here\n",
-                        vendor_id, product_id
-                    );
-
+                    // Save to file, keeping the rest of the configuration
                     println!("DEBUG: Writing config to file");
-                    std::fs::write(config_path, config_content)?;
+                    crate::core::save_device_ids(config_path, vendor_id, product_id)?;
                     println!("DEBUG: Config saved successfully");
                 }
                 (Err(e), _) | (_, Err(e)) => {